@@ -0,0 +1,231 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Result, Write};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use rasn_ldap::{
+    AttributeValueAssertion, AuthenticationChoice, Filter, LdapMessage, LdapResult, LdapString,
+    ProtocolOp, ResultCode, SubstringChoice,
+};
+
+use crate::log::{self, escape, format_timestamp};
+use crate::session::Session;
+
+/// Records every operation in the style of OpenLDAP's `loglevel stats`, so
+/// that existing tools for parsing slapd logs can read it, e.g.
+///
+/// ```text
+/// 2024-06-01T09:30:00Z conn=4 op=0 BIND dn="cn=admin" method=128
+/// 2024-06-01T09:30:00Z conn=4 op=0 RESULT tag=97 err=0 etime=0.000211 text=
+/// ```
+///
+/// Operations are numbered from zero on each connection, in the order they
+/// were received.
+pub struct AccessLog {
+    file: Mutex<File>,
+}
+
+impl AccessLog {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(AccessLog {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn connection_opened(&self, session: &Session, local: SocketAddr) {
+        self.write(format!(
+            "conn={} ACCEPT from IP={} (IP={local})",
+            session.id, session.peer
+        ));
+    }
+
+    pub fn connection_closed(&self, session: &Session) {
+        self.write(format!("conn={} closed", session.id));
+    }
+
+    pub fn request(&self, session: &Session, op: u64, req: &ProtocolOp) {
+        let lines = match req {
+            ProtocolOp::BindRequest(req) => {
+                let method = match req.authentication {
+                    AuthenticationChoice::Simple(_) => 128,
+                    _ => 163,
+                };
+                vec![format!(
+                    "BIND dn=\"{}\" method={method}",
+                    escape(&req.name, QUOTED)
+                )]
+            }
+            ProtocolOp::UnbindRequest(_) => vec!["UNBIND".to_string()],
+            ProtocolOp::SearchRequest(req) => {
+                let mut lines = vec![format!(
+                    "SRCH base=\"{}\" scope={} deref={} filter=\"{}\"",
+                    escape(&req.base_object, QUOTED),
+                    req.scope as u8,
+                    req.deref_aliases as u8,
+                    filter_string(&req.filter),
+                )];
+                if !req.attributes.is_empty() {
+                    let attrs: Vec<_> = req.attributes.iter().map(|a| escape(a, BARE)).collect();
+                    lines.push(format!("SRCH attr={}", attrs.join(" ")));
+                }
+                lines
+            }
+            ProtocolOp::ModifyRequest(req) => {
+                let attrs: Vec<_> = req
+                    .changes
+                    .iter()
+                    .map(|c| escape(&c.modification.r#type, BARE))
+                    .collect();
+                vec![
+                    format!("MOD dn=\"{}\"", escape(&req.object, QUOTED)),
+                    format!("MOD attr={}", attrs.join(" ")),
+                ]
+            }
+            ProtocolOp::AddRequest(req) => {
+                vec![format!("ADD dn=\"{}\"", escape(&req.entry, QUOTED))]
+            }
+            ProtocolOp::DelRequest(req) => vec![format!("DEL dn=\"{}\"", escape(&req.0, QUOTED))],
+            ProtocolOp::ModDnRequest(req) => {
+                vec![format!("MODRDN dn=\"{}\"", escape(&req.entry, QUOTED))]
+            }
+            ProtocolOp::CompareRequest(req) => vec![format!(
+                "CMP dn=\"{}\" attr=\"{}\"",
+                escape(&req.entry, QUOTED),
+                escape(&req.ava.attribute_desc, QUOTED)
+            )],
+            ProtocolOp::AbandonRequest(req) => vec![format!("ABANDON msg={}", req.0)],
+            ProtocolOp::ExtendedReq(req) => {
+                vec![format!("EXT oid={}", escape(&req.request_name, BARE))]
+            }
+            _ => return,
+        };
+
+        for line in lines {
+            self.write(format!("conn={} op={op} {line}", session.id));
+        }
+    }
+
+    /// Records the result of an operation from its responses, `elapsed` after
+    /// it was received
+    pub fn result(&self, session: &Session, op: u64, res: &[LdapMessage], elapsed: Duration) {
+        let Some(done) = res.last() else {
+            return;
+        };
+
+        let (tag, code, text) = match &done.protocol_op {
+            ProtocolOp::BindResponse(res) => (97, res.result_code, &res.diagnostic_message),
+            ProtocolOp::SearchResDone(res) => result(101, &res.0),
+            ProtocolOp::ModifyResponse(res) => result(103, &res.0),
+            ProtocolOp::AddResponse(res) => result(105, &res.0),
+            ProtocolOp::DelResponse(res) => result(107, &res.0),
+            ProtocolOp::ModDnResponse(res) => result(109, &res.0),
+            ProtocolOp::CompareResponse(res) => result(111, &res.0),
+            ProtocolOp::ExtendedResp(res) => (120, res.result_code, &res.diagnostic_message),
+            _ => return,
+        };
+
+        let (kind, entries) = match tag {
+            101 => {
+                let n = res
+                    .iter()
+                    .filter(|m| matches!(m.protocol_op, ProtocolOp::SearchResEntry(_)))
+                    .count();
+                ("SEARCH RESULT", format!(" nentries={n}"))
+            }
+            _ => ("RESULT", String::new()),
+        };
+
+        self.write(format!(
+            "conn={} op={op} {kind} tag={tag} err={} etime={:.6}{entries} text={}",
+            session.id,
+            code as u8,
+            elapsed.as_secs_f64(),
+            escape(text, QUOTED),
+        ));
+    }
+
+    fn write(&self, line: String) {
+        let line = format!("{} {line}\n", format_timestamp(SystemTime::now()));
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            log::error!("failed to write access log: {e}");
+        }
+    }
+}
+
+fn result(tag: u8, res: &LdapResult) -> (u8, ResultCode, &LdapString) {
+    (tag, res.result_code, &res.diagnostic_message)
+}
+
+/// Characters escaped in values written between quotes, or at the end of a line
+const QUOTED: &[char] = &['"', '\\'];
+/// Characters escaped in values written bare, as lists separated by spaces
+const BARE: &[char] = &['"', '\\', ' '];
+/// Characters escaped in the values of a filter, which is written quoted
+const FILTER: &[char] = &['"', '\\', '*', '(', ')'];
+
+/// Renders a filter in its RFC 4515 string form
+fn filter_string(filter: &Filter) -> String {
+    match filter {
+        Filter::And(filters) => format!(
+            "(&{})",
+            filters.iter().map(filter_string).collect::<String>()
+        ),
+        Filter::Or(filters) => format!(
+            "(|{})",
+            filters.iter().map(filter_string).collect::<String>()
+        ),
+        Filter::Not(filter) => format!("(!{})", filter_string(filter)),
+        Filter::EqualityMatch(ava) => assertion_string(ava, "="),
+        Filter::GreaterOrEqual(ava) => assertion_string(ava, ">="),
+        Filter::LessOrEqual(ava) => assertion_string(ava, "<="),
+        Filter::ApproxMatch(ava) => assertion_string(ava, "~="),
+        Filter::Present(attr) => format!("({}=*)", escape(attr, FILTER)),
+        Filter::Substrings(sub) => {
+            let mut out = format!("({}=", escape(&sub.r#type, FILTER));
+            for choice in &sub.substrings {
+                match choice {
+                    SubstringChoice::Initial(v) => out.push_str(&escape(v, FILTER)),
+                    SubstringChoice::Any(v) | SubstringChoice::Final(v) => {
+                        out.push('*');
+                        out.push_str(&escape(v, FILTER));
+                    }
+                    _ => {}
+                }
+            }
+            if !matches!(sub.substrings.last(), Some(SubstringChoice::Final(_))) {
+                out.push('*');
+            }
+            out.push(')');
+            out
+        }
+        Filter::ExtensibleMatch(m) => {
+            let mut out = format!(
+                "({}",
+                m.r#type
+                    .as_deref()
+                    .map(|t| escape(t, FILTER))
+                    .unwrap_or_default()
+            );
+            if m.dn_attributes {
+                out.push_str(":dn");
+            }
+            if let Some(rule) = &m.matching_rule {
+                out.push(':');
+                out.push_str(&escape(rule, FILTER));
+            }
+            format!("{out}:={})", escape(&m.match_value, FILTER))
+        }
+        // what slapd shows for a filter it can't make sense of
+        _ => "(?=undefined)".to_string(),
+    }
+}
+
+fn assertion_string(ava: &AttributeValueAssertion, op: &str) -> String {
+    format!(
+        "({}{op}{})",
+        escape(&ava.attribute_desc, FILTER),
+        escape(&ava.assertion_value, FILTER)
+    )
+}
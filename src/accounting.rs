@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::ops::AddAssign;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rasn_ldap::ResultCode;
+
+use crate::dn;
+use crate::error::LdapError;
+use crate::session::Session;
+
+const QUOTA_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Bytes transferred with a client
+#[derive(Default, Clone, Copy)]
+pub struct Traffic {
+    pub read: u64,
+    pub written: u64,
+}
+
+impl AddAssign for Traffic {
+    fn add_assign(&mut self, other: Traffic) {
+        self.read += other.read;
+        self.written += other.written;
+    }
+}
+
+/// Keeps track of the traffic written to each bind identity (or, for
+/// anonymous clients, each address) in the current hour, optionally limiting
+/// how much can be returned to one identity per hour
+pub struct TrafficAccounting {
+    hourly_quota: Option<u64>,
+    windows: Mutex<Windows>,
+}
+
+struct Windows {
+    by_identity: HashMap<String, Window>,
+    /// When windows that have ended were last removed
+    last_swept: Instant,
+}
+
+struct Window {
+    start: Instant,
+    written: u64,
+}
+
+impl TrafficAccounting {
+    pub fn new(hourly_quota: Option<u64>) -> Self {
+        TrafficAccounting {
+            hourly_quota,
+            windows: Mutex::new(Windows {
+                by_identity: HashMap::new(),
+                last_swept: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn record(&self, session: &mut Session, traffic: Traffic) {
+        // only what is returned counts towards a quota
+        if traffic.written == 0 {
+            return;
+        }
+
+        let identity = identity(session);
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+
+        // an identity whose window has ended has nothing left to limit, so
+        // forgetting it keeps the map from growing with every client ever seen
+        if now.duration_since(windows.last_swept) >= QUOTA_WINDOW {
+            windows
+                .by_identity
+                .retain(|_, w| now.duration_since(w.start) < QUOTA_WINDOW);
+            windows.last_swept = now;
+        }
+
+        let w = windows
+            .by_identity
+            .entry(identity.clone())
+            .or_insert(Window {
+                start: now,
+                written: 0,
+            });
+        if now.duration_since(w.start) >= QUOTA_WINDOW {
+            w.start = now;
+            w.written = 0;
+        }
+        w.written += traffic.written;
+
+        if !session.charged_identities.contains(&identity) {
+            session.charged_identities.push(identity);
+        }
+    }
+
+    /// Checks the quota of every identity the connection has used, so that
+    /// one which has run out can't be escaped by binding as another
+    pub fn check_quota(&self, session: &Session) -> Result<(), LdapError> {
+        let Some(quota) = self.hourly_quota else {
+            return Ok(());
+        };
+
+        let windows = self.windows.lock().unwrap();
+        let exceeded = session
+            .charged_identities
+            .iter()
+            .chain([&identity(session)])
+            .filter_map(|identity| windows.by_identity.get(identity))
+            .any(|w| w.start.elapsed() < QUOTA_WINDOW && w.written >= quota);
+
+        if exceeded {
+            return Err(LdapError::new(
+                ResultCode::AdminLimitExceeded,
+                "hourly transfer quota exceeded",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn identity(session: &Session) -> String {
+    match &session.bind_dn {
+        Some(bind_dn) => dn::normalise(bind_dn),
+        None => format!("anonymous@{}", session.peer.ip()),
+    }
+}
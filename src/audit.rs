@@ -0,0 +1,73 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Result, Write};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use rasn_ldap::{LdapDn, LdapMessage, ProtocolOp, ResultCode};
+
+use crate::log::{self, escape, format_timestamp};
+use crate::session::Session;
+
+/// Records binds and writes to a dedicated file, one line per operation,
+/// kept separate from the server's diagnostic output
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(AuditLog {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Records `req` if it is an audited operation, along with the result
+    /// code from its response
+    pub fn record(&self, session: &Session, req: &ProtocolOp, res: Option<&LdapMessage>) {
+        let (Some((op, target)), Some(code)) = (audited_op(req), res.and_then(result_code)) else {
+            return;
+        };
+
+        let line = format!(
+            "{} op={op} client={} identity=\"{}\" target=\"{}\" result={} ({code:?})\n",
+            format_timestamp(SystemTime::now()),
+            session.peer,
+            escape(
+                session.bind_dn.as_deref().unwrap_or("anonymous").as_bytes(),
+                QUOTED
+            ),
+            escape(target, QUOTED),
+            code as u8,
+        );
+
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            log::error!("failed to write audit log: {e}");
+        }
+    }
+}
+
+/// Characters escaped in the quoted fields of a line
+const QUOTED: &[char] = &['"', '\\'];
+
+fn audited_op(op: &ProtocolOp) -> Option<(&'static str, &LdapDn)> {
+    match op {
+        ProtocolOp::BindRequest(req) => Some(("bind", &req.name)),
+        ProtocolOp::AddRequest(req) => Some(("add", &req.entry)),
+        ProtocolOp::ModifyRequest(req) => Some(("modify", &req.object)),
+        ProtocolOp::DelRequest(req) => Some(("delete", &req.0)),
+        ProtocolOp::ModDnRequest(req) => Some(("modrdn", &req.entry)),
+        _ => None,
+    }
+}
+
+fn result_code(res: &LdapMessage) -> Option<ResultCode> {
+    match &res.protocol_op {
+        ProtocolOp::BindResponse(res) => Some(res.result_code),
+        ProtocolOp::AddResponse(res) => Some(res.0.result_code),
+        ProtocolOp::ModifyResponse(res) => Some(res.0.result_code),
+        ProtocolOp::DelResponse(res) => Some(res.0.result_code),
+        ProtocolOp::ModDnResponse(res) => Some(res.0.result_code),
+        _ => None,
+    }
+}
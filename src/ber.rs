@@ -0,0 +1,307 @@
+use std::mem;
+
+/// How deeply indefinite length elements may nest before a message is
+/// treated as malformed
+const MAX_INDEFINITE_DEPTH: usize = 32;
+
+#[derive(Debug)]
+pub enum FrameError {
+    /// The element is, or is declared to be, longer than allowed
+    TooLarge(usize),
+    /// The bytes cannot be the start of a BER element
+    Malformed(&'static str),
+}
+
+/// The identifier and length octets at the start of an element
+struct Header {
+    len: usize,
+    /// The length of the contents, or `None` if they are terminated by an
+    /// end-of-contents marker instead
+    content_len: Option<usize>,
+}
+
+/// Finds where each BER element read from a stream ends, as its bytes
+/// arrive.
+///
+/// The elements inside indefinite length ones are walked as they come in, and
+/// how far that got is remembered between calls, so that a message trickling
+/// in a few bytes at a time is still only looked at once.
+#[derive(Default)]
+pub struct Framer {
+    /// How far into the buffer has been walked
+    pos: usize,
+    /// How many indefinite length elements are open at `pos`
+    depth: usize,
+}
+
+impl Framer {
+    /// Works out how many bytes at the start of `buf` make up a complete BER
+    /// element, or returns `None` if more bytes are needed to tell. `buf`
+    /// must hold what it did at the last call, plus anything read since, up
+    /// until an element is found, after which the framer starts afresh.
+    ///
+    /// The length of an element is checked against `max` as soon as it is
+    /// known, so that a client can't make the server buffer an unbounded
+    /// amount of data before it finds out the message is too large.
+    pub fn element_len(&mut self, buf: &[u8], max: usize) -> Result<Option<usize>, FrameError> {
+        let len = self.walk(buf, max)?;
+        if len.is_some() {
+            *self = Framer::default();
+        }
+        Ok(len)
+    }
+
+    fn walk(&mut self, buf: &[u8], max: usize) -> Result<Option<usize>, FrameError> {
+        loop {
+            let rest = &buf[self.pos..];
+
+            // an end-of-contents marker (two zero octets) closes the innermost
+            // indefinite length element
+            if self.depth > 0 && rest.starts_with(&[0, 0]) {
+                self.pos += 2;
+                self.depth -= 1;
+                if self.depth == 0 {
+                    return Ok(Some(self.pos));
+                }
+                continue;
+            }
+
+            let Some(header) = read_header(rest)? else {
+                break;
+            };
+
+            let Some(content_len) = header.content_len else {
+                if self.depth >= MAX_INDEFINITE_DEPTH {
+                    return Err(FrameError::Malformed(
+                        "indefinite length elements nested too deeply",
+                    ));
+                }
+                self.depth += 1;
+                self.pos += header.len;
+                continue;
+            };
+
+            let end = header
+                .len
+                .checked_add(content_len)
+                .and_then(|len| len.checked_add(self.pos))
+                .ok_or(FrameError::TooLarge(usize::MAX))?;
+            if end > max {
+                return Err(FrameError::TooLarge(end));
+            }
+            if buf.len() < end {
+                break;
+            }
+
+            // an element inside an indefinite length one is skipped over whole,
+            // as only the outermost element's end matters
+            self.pos = end;
+            if self.depth == 0 {
+                return Ok(Some(end));
+            }
+        }
+
+        // the end hasn't arrived yet, but what has must still be within the limit
+        if buf.len() > max {
+            return Err(FrameError::TooLarge(buf.len()));
+        }
+
+        Ok(None)
+    }
+}
+
+fn read_header(buf: &[u8]) -> Result<Option<Header>, FrameError> {
+    let Some(&identifier) = buf.first() else {
+        return Ok(None);
+    };
+
+    // the low five bits all set means the tag number continues in the
+    // following octets, each with the high bit set except the last
+    let mut pos = 1;
+    if identifier & 0x1f == 0x1f {
+        loop {
+            let Some(&b) = buf.get(pos) else {
+                return Ok(None);
+            };
+            pos += 1;
+            if b & 0x80 == 0 {
+                break;
+            }
+            if pos > 1 + mem::size_of::<u32>() {
+                return Err(FrameError::Malformed("BER tag number is too long"));
+            }
+        }
+    }
+
+    let Some(&first) = buf.get(pos) else {
+        return Ok(None);
+    };
+    pos += 1;
+
+    if first & 0x80 == 0 {
+        return Ok(Some(Header {
+            len: pos,
+            content_len: Some(first as usize),
+        }));
+    }
+
+    let octets = (first & 0x7f) as usize;
+    if octets == 0 {
+        if identifier & 0x20 == 0 {
+            return Err(FrameError::Malformed(
+                "indefinite length used for a primitive element",
+            ));
+        }
+        return Ok(Some(Header {
+            len: pos,
+            content_len: None,
+        }));
+    }
+    if octets == 0x7f {
+        return Err(FrameError::Malformed("reserved BER length octet"));
+    }
+    if octets > mem::size_of::<usize>() {
+        return Err(FrameError::TooLarge(usize::MAX));
+    }
+
+    let Some(len_octets) = buf.get(pos..pos + octets) else {
+        return Ok(None);
+    };
+
+    Ok(Some(Header {
+        len: pos + octets,
+        content_len: Some(len_octets.iter().fold(0, |len, b| (len << 8) | *b as usize)),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX: usize = 1024;
+
+    fn element_len(buf: &[u8], max: usize) -> Result<Option<usize>, FrameError> {
+        Framer::default().element_len(buf, max)
+    }
+
+    #[test]
+    fn fragmented() {
+        let msg = [0x30, 0x05, 0x02, 0x01, 0x01, 0x42, 0x00];
+        for end in 0..msg.len() {
+            assert!(matches!(element_len(&msg[..end], MAX), Ok(None)));
+        }
+        assert!(matches!(element_len(&msg, MAX), Ok(Some(7))));
+
+        // whatever follows the first element is left for the next one
+        let pipelined = [&msg[..], &msg[..3]].concat();
+        assert!(matches!(element_len(&pipelined, MAX), Ok(Some(7))));
+    }
+
+    #[test]
+    fn high_tag_numbers() {
+        let msg = [0x5f, 0x81, 0x00, 0x01, 0xff];
+        assert!(matches!(element_len(&msg[..2], MAX), Ok(None)));
+        assert!(matches!(element_len(&msg, MAX), Ok(Some(5))));
+
+        let too_long = [0x5f, 0x81, 0x81, 0x81, 0x81, 0x81, 0x00, 0x00];
+        assert!(matches!(
+            element_len(&too_long, MAX),
+            Err(FrameError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn long_form_lengths() {
+        let mut msg = vec![0x04, 0x82, 0x01, 0x00];
+        msg.resize(4 + 0x100, 0);
+        assert!(matches!(element_len(&msg[..3], MAX), Ok(None)));
+        assert!(matches!(element_len(&msg[..100], MAX), Ok(None)));
+        assert!(matches!(element_len(&msg, MAX), Ok(Some(0x104))));
+
+        assert!(matches!(
+            element_len(&[0x04, 0xff], MAX),
+            Err(FrameError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn nested_indefinite_lengths() {
+        let msg = [
+            0x30, 0x80, 0x02, 0x01, 0x01, 0x30, 0x80, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        for end in 0..msg.len() {
+            assert!(matches!(element_len(&msg[..end], MAX), Ok(None)));
+        }
+        assert!(matches!(element_len(&msg, MAX), Ok(Some(13))));
+
+        assert!(matches!(
+            element_len(&[0x04, 0x80, 0x00, 0x00], MAX),
+            Err(FrameError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn incremental() {
+        let msg = [
+            0x30, 0x80, 0x04, 0x00, 0x30, 0x80, 0x04, 0x02, 0xaa, 0xbb, 0x00, 0x00, 0x04, 0x00,
+            0x00, 0x00,
+        ];
+        let stream = [&msg[..], &[0x30, 0x03, 0x02, 0x01, 0x07]].concat();
+
+        // fed a byte at a time, what has already been walked isn't walked again
+        let mut framer = Framer::default();
+        let mut walked = 0;
+        for end in 1..msg.len() {
+            assert!(matches!(framer.element_len(&stream[..end], MAX), Ok(None)));
+            assert!(framer.pos >= walked);
+            walked = framer.pos;
+        }
+        assert!(matches!(framer.element_len(&msg, MAX), Ok(Some(16))));
+
+        // and once an element is found, the framer starts afresh on the next
+        let next = &stream[msg.len()..];
+        assert!(matches!(framer.element_len(&next[..2], MAX), Ok(None)));
+        assert!(matches!(framer.element_len(next, MAX), Ok(Some(5))));
+    }
+
+    #[test]
+    fn depth_cap() {
+        let nested =
+            |depth: usize| [[0x30, 0x80].repeat(depth), [0x00, 0x00].repeat(depth)].concat();
+
+        let allowed = nested(MAX_INDEFINITE_DEPTH);
+        assert!(matches!(element_len(&allowed, MAX), Ok(Some(n)) if n == allowed.len()));
+        assert!(matches!(
+            element_len(&nested(MAX_INDEFINITE_DEPTH + 1), MAX),
+            Err(FrameError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn too_large() {
+        // a definite length is refused from its header alone
+        assert!(matches!(
+            element_len(&[0x30, 0x82, 0x04, 0x00], MAX),
+            Err(FrameError::TooLarge(0x404))
+        ));
+        assert!(matches!(
+            element_len(
+                &[0x30, 0x88, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+                MAX
+            ),
+            Err(FrameError::TooLarge(_))
+        ));
+
+        // an indefinite length is refused once more than the limit arrives
+        // without an end-of-contents marker
+        let mut msg = vec![0x30, 0x80];
+        while msg.len() <= MAX {
+            msg.extend([0x04, 0x02, 0xaa, 0xbb]);
+        }
+        assert!(matches!(
+            element_len(&msg, MAX),
+            Err(FrameError::TooLarge(_))
+        ));
+        assert!(matches!(element_len(&msg[..MAX - 2], MAX), Ok(None)));
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rasn_ldap::ResultCode;
+
+use crate::config::CalloutConfig;
+use crate::dn;
+use crate::error::LdapError;
+use crate::password::constant_time_eq;
+
+/// Delegates password checks for a subtree to an external command.
+///
+/// The command is run with the bind DN as its only argument and the password
+/// on stdin, and the password is accepted if it exits successfully. Successful
+/// checks are cached for a while so that every bind doesn't spawn a process.
+pub struct PasswordCallout {
+    subtree: String,
+    command: String,
+    timeout: Duration,
+    cache_ttl: Duration,
+    cache: Mutex<HashMap<String, (Vec<u8>, Instant)>>,
+}
+
+impl PasswordCallout {
+    pub fn new(config: &CalloutConfig, timeout: Duration, cache_ttl: Duration) -> Self {
+        PasswordCallout {
+            subtree: config.subtree.clone(),
+            command: config.command.clone(),
+            timeout,
+            cache_ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn covers(&self, dn: &str) -> bool {
+        dn::is_within(dn, &self.subtree)
+    }
+
+    pub fn verify(&self, dn: &str, password: &[u8]) -> Result<bool, LdapError> {
+        let key = dn::normalise(dn);
+
+        if let Some((cached, at)) = self.cache.lock().unwrap().get(&key) {
+            if constant_time_eq(cached, password) && at.elapsed() < self.cache_ttl {
+                return Ok(true);
+            }
+        }
+
+        let valid = self.run_command(dn, password)?;
+        if valid && !self.cache_ttl.is_zero() {
+            self.cache
+                .lock()
+                .unwrap()
+                .insert(key, (password.to_vec(), Instant::now()));
+        }
+
+        Ok(valid)
+    }
+
+    fn run_command(&self, dn: &str, password: &[u8]) -> Result<bool, LdapError> {
+        let unavailable = |reason: String| {
+            LdapError::new(
+                ResultCode::Unavailable,
+                format!("password verification unavailable: {reason}"),
+            )
+        };
+
+        // a timeout too long to represent is no timeout at all
+        let deadline = Instant::now().checked_add(self.timeout);
+
+        let mut child = Command::new(&self.command)
+            .arg(dn)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| unavailable(e.to_string()))?;
+
+        // the command may exit without reading stdin, which is its answer, or
+        // not read it in time, so a long password can't block on a full pipe
+        // past the deadline. Once the command is gone the write fails and the
+        // thread ends.
+        if let Some(mut stdin) = child.stdin.take() {
+            let password = password.to_vec();
+            thread::spawn(move || {
+                let _ = stdin.write_all(&password);
+            });
+        }
+
+        loop {
+            match child.try_wait() {
+                Ok(Some(status)) => return Ok(status.success()),
+                Ok(None) if deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(unavailable("timed out".to_string()));
+                }
+                Ok(None) => thread::sleep(Duration::from_millis(10)),
+                Err(e) => return Err(unavailable(e.to_string())),
+            }
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use std::fs;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::dn;
+use crate::log::Level;
+
+pub struct Config {
+    pub listeners: Vec<ListenerConfig>,
+    pub root: Option<RootCredentials>,
+    /// The largest request accepted, in bytes
+    pub max_message_size: usize,
+    /// The most connections open at once across all listeners, if limited
+    pub max_connections: Option<usize>,
+    /// The most entries one search returns, whatever the client asks for
+    pub search_size_limit: Option<usize>,
+    /// The longest one search runs for, whatever the client asks for
+    pub search_time_limit: Option<Duration>,
+    /// The most bytes of entries one search returns, if limited
+    pub search_max_bytes: Option<u64>,
+    /// The most operations processed at once across all connections
+    pub max_operations: usize,
+    /// How long an operation waits for a free slot before getting `busy`
+    pub operation_wait: Duration,
+    /// Failed binds from one address or against one DN before further
+    /// attempts are refused, or zero to never refuse them
+    pub bind_max_failures: u32,
+    pub bind_failure_window: Duration,
+    pub bind_lockout: Duration,
+    pub password_callouts: Vec<CalloutConfig>,
+    /// The most bytes returned to one identity per hour, if limited
+    pub hourly_quota: Option<u64>,
+    /// Where to record binds and writes, if anywhere
+    pub audit_log: Option<String>,
+    /// Where to record every operation in slapd's format, if anywhere
+    pub access_log: Option<String>,
+    /// Where to answer HTTP health probes, if anywhere
+    pub health_listen: Option<String>,
+    /// How long a password callout may run before the bind is refused
+    pub callout_timeout: Duration,
+    /// How long a successful password callout is remembered for
+    pub callout_cache_ttl: Duration,
+    /// The most verbose messages written to stderr
+    pub log_level: Level,
+    /// Log every PDU sent and received on every connection. The dumps include
+    /// bind passwords, so this is only for debugging.
+    pub trace_wire: bool,
+    /// Log every PDU on connections from these addresses, as `trace_wire` does
+    /// for all of them
+    pub trace_wire_from: Vec<IpAddr>,
+}
+
+pub struct ListenerConfig {
+    pub addr: String,
+    pub policy: SecurityPolicy,
+}
+
+/// What kinds of bind a listener accepts, given on the command line as a list
+/// of policy names that are each either allowed or prefixed with `no-`
+#[derive(Clone, Copy)]
+pub struct SecurityPolicy {
+    /// Allow binds with no name and no password (`anonymous`)
+    pub anonymous: bool,
+    /// Allow binds with a name but no password, treating them as anonymous
+    /// (`unauthenticated`, RFC 4513 section 5.1.2)
+    pub unauthenticated: bool,
+    /// Allow simple binds carrying a password on connections without TLS
+    /// (`cleartext-binds`)
+    pub cleartext_binds: bool,
+}
+
+impl SecurityPolicy {
+    fn set(&mut self, name: &str) -> Result<(), String> {
+        let (policy, allow) = match name.strip_prefix("no-") {
+            Some(policy) => (policy, false),
+            None => (name, true),
+        };
+
+        match policy {
+            "anonymous" => self.anonymous = allow,
+            "unauthenticated" => self.unauthenticated = allow,
+            "cleartext-binds" => self.cleartext_binds = allow,
+            _ => return Err(format!("unknown listener policy {name}")),
+        }
+
+        Ok(())
+    }
+}
+
+/// The configured admin identity, which always authenticates with its
+/// configured password whether or not an entry exists for it
+pub struct RootCredentials {
+    pub dn: String,
+    pub password: String,
+}
+
+/// An external command that verifies passwords for binds within `subtree`
+pub struct CalloutConfig {
+    pub subtree: String,
+    pub command: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listeners: Vec::new(),
+            root: None,
+            max_message_size: 4 * 1024 * 1024,
+            max_connections: None,
+            search_size_limit: None,
+            search_time_limit: None,
+            search_max_bytes: None,
+            max_operations: 128,
+            operation_wait: Duration::from_secs(1),
+            bind_max_failures: 0,
+            bind_failure_window: Duration::from_secs(300),
+            bind_lockout: Duration::from_secs(300),
+            password_callouts: Vec::new(),
+            hourly_quota: None,
+            audit_log: None,
+            access_log: None,
+            health_listen: None,
+            callout_timeout: Duration::from_secs(5),
+            callout_cache_ttl: Duration::from_secs(60),
+            log_level: Level::Info,
+            trace_wire: false,
+            trace_wire_from: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Builds the config from command line arguments (without the program name)
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut config = Config::default();
+        let mut root_dn = None;
+        let mut root_pw_file = None;
+        let mut listeners = Vec::new();
+        let mut default_policy = SecurityPolicy {
+            anonymous: true,
+            unauthenticated: true,
+            cleartext_binds: true,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{arg} requires a value"));
+
+            match arg.as_str() {
+                "--listen" => {
+                    let value = value()?;
+                    let mut parts = value.split(',').map(str::to_string);
+                    let addr = parts.next().unwrap_or_default();
+                    listeners.push((addr, parts.collect::<Vec<_>>()));
+                }
+                "--root-dn" => root_dn = Some(parse_dn(&arg, value()?)?),
+                "--root-pw-file" => root_pw_file = Some(value()?),
+                "--reject-unauthenticated-binds" => default_policy.unauthenticated = false,
+                "--disallow-cleartext-binds" => default_policy.cleartext_binds = false,
+                "--max-message-size" => config.max_message_size = parse(&arg, value()?)?,
+                "--max-connections" => config.max_connections = Some(parse(&arg, value()?)?),
+                "--search-size-limit" => config.search_size_limit = Some(parse(&arg, value()?)?),
+                "--search-time-limit-secs" => {
+                    config.search_time_limit = Some(Duration::from_secs(parse(&arg, value()?)?))
+                }
+                "--search-max-mb" => {
+                    let max = parse::<u64>(&arg, value()?)?
+                        .checked_mul(1024 * 1024)
+                        .ok_or(format!("{arg} is too large"))?;
+                    config.search_max_bytes = Some(max)
+                }
+                "--max-operations" => config.max_operations = parse(&arg, value()?)?,
+                "--operation-wait-ms" => {
+                    config.operation_wait = Duration::from_millis(parse(&arg, value()?)?)
+                }
+                "--bind-max-failures" => config.bind_max_failures = parse(&arg, value()?)?,
+                "--bind-failure-window-secs" => {
+                    config.bind_failure_window = Duration::from_secs(parse(&arg, value()?)?)
+                }
+                "--bind-lockout-secs" => {
+                    config.bind_lockout = Duration::from_secs(parse(&arg, value()?)?)
+                }
+                "--password-callout" => {
+                    let value = value()?;
+                    let Some((subtree, command)) = value.split_once(':') else {
+                        return Err(format!("{arg} must be given as SUBTREE:COMMAND"));
+                    };
+                    config.password_callouts.push(CalloutConfig {
+                        subtree: parse_dn(&arg, subtree.to_string())?,
+                        command: command.to_string(),
+                    });
+                }
+                "--callout-timeout-ms" => {
+                    config.callout_timeout = Duration::from_millis(parse(&arg, value()?)?)
+                }
+                "--callout-cache-secs" => {
+                    config.callout_cache_ttl = Duration::from_secs(parse(&arg, value()?)?)
+                }
+                "--hourly-quota-mb" => {
+                    let quota = parse::<u64>(&arg, value()?)?
+                        .checked_mul(1024 * 1024)
+                        .ok_or(format!("{arg} is too large"))?;
+                    config.hourly_quota = Some(quota)
+                }
+                "--audit-log" => config.audit_log = Some(value()?),
+                "--access-log" => config.access_log = Some(value()?),
+                "--health-listen" => config.health_listen = Some(value()?),
+                "--log-level" => config.log_level = parse(&arg, value()?)?,
+                "--trace-wire" => config.trace_wire = true,
+                "--trace-wire-from" => config.trace_wire_from.push(parse(&arg, value()?)?),
+                _ => return Err(format!("unknown argument {arg}")),
+            }
+        }
+
+        if config.max_operations == 0 {
+            return Err("--max-operations must be at least 1".to_string());
+        }
+
+        // listener policies are applied on top of whatever the global flags set,
+        // wherever they appear on the command line
+        if listeners.is_empty() {
+            listeners.push(("127.0.0.1:8000".to_string(), Vec::new()));
+        }
+
+        for (addr, policies) in listeners {
+            let mut policy = default_policy;
+            for name in policies {
+                policy.set(&name)?;
+            }
+            config.listeners.push(ListenerConfig { addr, policy });
+        }
+
+        config.root = match (root_dn, root_pw_file) {
+            (Some(dn), Some(path)) => Some(RootCredentials {
+                dn,
+                password: read_password_file(&path)?,
+            }),
+            (None, None) => None,
+            _ => return Err("--root-dn and --root-pw-file must be given together".to_string()),
+        };
+
+        Ok(config)
+    }
+}
+
+fn parse<T: FromStr>(arg: &str, value: String) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {arg}: {value}"))
+}
+
+/// Checks that a DN parses, as one that doesn't can never match any other
+fn parse_dn(arg: &str, value: String) -> Result<String, String> {
+    match dn::parse(&value) {
+        Ok(_) => Ok(value),
+        Err(e) => Err(format!("invalid DN for {arg}: {}", e.message)),
+    }
+}
+
+/// Reads a password from the first line of a file, so that it does not have
+/// to appear on the command line
+fn read_password_file(path: &str) -> Result<String, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("could not read {path}: {e}"))?;
+
+    match contents.lines().next() {
+        Some(pw) if !pw.is_empty() => Ok(pw.to_string()),
+        _ => Err(format!("{path} does not contain a password")),
+    }
+}
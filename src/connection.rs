@@ -0,0 +1,311 @@
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use rasn::ber::{de, enc};
+use rasn::prelude::*;
+use rasn_ldap::{ExtendedResponse, LdapMessage, ProtocolOp, ResultCode};
+
+use crate::access::AccessLog;
+use crate::accounting::Traffic;
+use crate::ber::{FrameError, Framer};
+use crate::config::SecurityPolicy;
+use crate::controller::LdapController;
+use crate::log;
+use crate::session::Session;
+
+/// The unsolicited notification sent before the server closes a connection
+/// (RFC 4511 section 4.4.1)
+const NOTICE_OF_DISCONNECTION_OID: &str = "1.3.6.1.4.1.1466.20036";
+
+const READ_CHUNK_SIZE: usize = 4096;
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+pub struct LdapTcpConnection {
+    stream: TcpStream,
+    controller: Arc<LdapController>,
+    session: Session,
+    /// Bytes read from the stream that aren't yet part of a complete message
+    buf: Vec<u8>,
+    framer: Framer,
+    /// Whether every PDU is logged, see `Config::trace_wire`
+    trace: bool,
+}
+
+impl LdapTcpConnection {
+    pub fn new(
+        stream: TcpStream,
+        peer: SocketAddr,
+        policy: SecurityPolicy,
+        controller: Arc<LdapController>,
+    ) -> Self {
+        let config = controller.config();
+        let trace = config.trace_wire || config.trace_wire_from.contains(&peer.ip());
+
+        LdapTcpConnection {
+            stream,
+            controller,
+            session: Session {
+                id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+                peer,
+                secure: false,
+                policy,
+                bind_dn: None,
+                traffic: Traffic::default(),
+                charged_identities: Vec::new(),
+            },
+            buf: Vec::new(),
+            framer: Framer::default(),
+            trace,
+        }
+    }
+
+    /// Serves requests until the client unbinds or closes the connection
+    pub fn run(&mut self) -> Result<()> {
+        let controller = self.controller.clone();
+        let access = controller.access_log();
+
+        if let Some(access) = access {
+            access.connection_opened(&self.session, self.stream.local_addr()?);
+        }
+
+        let res = self.serve_requests(access);
+
+        if let Some(access) = access {
+            access.connection_closed(&self.session);
+        }
+
+        res
+    }
+
+    fn serve_requests(&mut self, access: Option<&AccessLog>) -> Result<()> {
+        let mut op = 0;
+        while let Some(msg) = self.read_msg()? {
+            let _span = log::span(format!("msgid={}", msg.message_id));
+            log::debug!("received {}", request_name(&msg.protocol_op));
+
+            if let Some(access) = access {
+                access.request(&self.session, op, &msg.protocol_op);
+            }
+
+            let monitor = self.controller.monitor();
+            monitor.operation_initiated(&msg.protocol_op);
+
+            if let ProtocolOp::UnbindRequest(_) = msg.protocol_op {
+                monitor.operation_completed(&msg.protocol_op);
+                break;
+            }
+
+            let received = Instant::now();
+            let req = msg.protocol_op.clone();
+            let responses = self.controller.handle_ldap_message(&mut self.session, msg);
+            if let Some(access) = access {
+                access.result(&self.session, op, &responses, received.elapsed());
+            }
+            for res in responses {
+                self.write_msg(res)?;
+            }
+            self.controller.monitor().operation_completed(&req);
+
+            op += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Tells the client the server is about to close the connection, and why
+    pub fn disconnect(&mut self, code: ResultCode, reason: &'static str) -> Result<()> {
+        let notice = ProtocolOp::ExtendedResp(ExtendedResponse {
+            result_code: code,
+            matched_dn: "".into(),
+            diagnostic_message: reason.into(),
+            referral: None,
+            response_name: Some(NOTICE_OF_DISCONNECTION_OID.into()),
+            response_value: None,
+        });
+
+        self.write_msg(LdapMessage::new(0, notice))
+    }
+
+    pub fn id(&self) -> u64 {
+        self.session.id
+    }
+
+    pub fn traffic(&self) -> Traffic {
+        self.session.traffic
+    }
+
+    fn record_traffic(&mut self, traffic: Traffic) {
+        self.session.traffic += traffic;
+        self.controller.record_traffic(&mut self.session, traffic);
+    }
+
+    /// Reads the next message, or `None` if the client has closed the connection
+    fn read_msg(&mut self) -> Result<Option<LdapMessage>> {
+        let max = self.controller.config().max_message_size;
+
+        loop {
+            match self.framer.element_len(&self.buf, max) {
+                Ok(Some(len)) => {
+                    // dumped before decoding, as the bytes of a PDU that won't
+                    // decode are the most useful to see
+                    if self.trace {
+                        log::info!("received {len} bytes: {}", hex::encode(&self.buf[..len]));
+                    }
+
+                    let mut ber_decoder =
+                        de::Decoder::new(&self.buf[..len], de::DecoderOptions::ber());
+                    let msg = match LdapMessage::decode(&mut ber_decoder) {
+                        Ok(msg) => msg,
+                        Err(e) => return Err(self.protocol_error(e.to_string())),
+                    };
+
+                    if self.trace {
+                        log::info!("received {msg:?}");
+                    }
+
+                    // anything after the message is the start of the next one
+                    self.buf.drain(..len);
+                    return Ok(Some(msg));
+                }
+                Ok(None) => {}
+                Err(FrameError::TooLarge(len)) => {
+                    self.disconnect(ResultCode::AdminLimitExceeded, "request too large")?;
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("request of {len} bytes exceeds the limit of {max}"),
+                    ));
+                }
+                Err(FrameError::Malformed(reason)) => return Err(self.protocol_error(reason)),
+            }
+
+            let mut chunk = [0; READ_CHUNK_SIZE];
+            let n = match self.stream.read(&mut chunk) {
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                r => r?,
+            };
+
+            if n == 0 {
+                return match self.buf.is_empty() {
+                    true => Ok(None),
+                    false => Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "connection closed part way through a message",
+                    )),
+                };
+            }
+
+            self.buf.extend_from_slice(&chunk[..n]);
+            self.record_traffic(Traffic {
+                read: n as u64,
+                written: 0,
+            });
+        }
+    }
+
+    /// Ends the session over a PDU that can't be understood, after telling the
+    /// client why. Even when the message can be framed there's no knowing what
+    /// the client meant by it, so the session can't safely carry on (RFC 4511
+    /// section 4.1.1)
+    fn protocol_error(&mut self, reason: impl Into<String>) -> Error {
+        let reason = reason.into();
+        if let Err(e) = self.disconnect(ResultCode::ProtocolError, "malformed request") {
+            return e;
+        }
+
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("malformed request: {reason}"),
+        )
+    }
+
+    fn write_msg(&mut self, msg: LdapMessage) -> Result<()> {
+        let mut ber_encoder = enc::Encoder::new(enc::EncoderOptions::ber());
+        msg.encode(&mut ber_encoder)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+
+        let out = ber_encoder.output();
+        if self.trace {
+            log::info!("sending {msg:?}");
+            log::info!("sending {} bytes: {}", out.len(), hex::encode(&out));
+        }
+        self.controller.monitor().record_sent(&msg, out.len());
+
+        self.stream.write_all(&out)?;
+        self.record_traffic(Traffic {
+            read: 0,
+            written: out.len() as u64,
+        });
+
+        Ok(())
+    }
+}
+
+/// Names the kind of request, for logging without giving away its contents
+fn request_name(op: &ProtocolOp) -> &'static str {
+    match op {
+        ProtocolOp::BindRequest(_) => "bind",
+        ProtocolOp::UnbindRequest(_) => "unbind",
+        ProtocolOp::SearchRequest(_) => "search",
+        ProtocolOp::ModifyRequest(_) => "modify",
+        ProtocolOp::AddRequest(_) => "add",
+        ProtocolOp::DelRequest(_) => "delete",
+        ProtocolOp::ModDnRequest(_) => "modrdn",
+        ProtocolOp::CompareRequest(_) => "compare",
+        ProtocolOp::AbandonRequest(_) => "abandon",
+        ProtocolOp::ExtendedReq(_) => "extended",
+        _ => "non-request",
+    }
+}
+
+/// Counts the open connections across all listeners, optionally refusing
+/// more than a maximum
+pub struct ConnectionCounter {
+    open: Arc<AtomicUsize>,
+    max: Option<usize>,
+    /// Connections accepted since the server started
+    total: AtomicU64,
+}
+
+/// An open connection, which stops being counted when dropped
+pub struct ConnectionGuard(Arc<AtomicUsize>);
+
+impl ConnectionCounter {
+    pub fn new(max: Option<usize>) -> Self {
+        ConnectionCounter {
+            open: Arc::new(AtomicUsize::new(0)),
+            max,
+            total: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts a new connection, or returns `None` if there are already as
+    /// many open as allowed
+    pub fn try_open(&self) -> Option<ConnectionGuard> {
+        let open = self.open.fetch_add(1, Ordering::SeqCst);
+        if self.max.is_some_and(|max| open >= max) {
+            self.open.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+
+        self.total.fetch_add(1, Ordering::Relaxed);
+        Some(ConnectionGuard(self.open.clone()))
+    }
+
+    pub fn open(&self) -> usize {
+        self.open.load(Ordering::SeqCst)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
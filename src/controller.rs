@@ -0,0 +1,391 @@
+use rasn_ldap::{
+    AuthenticationChoice, BindRequest, BindResponse, LdapMessage, LdapResult, MessageId,
+    ProtocolOp, ResultCode, SearchRequest, SearchRequestScope, SearchResultDone,
+};
+
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::access::AccessLog;
+use crate::accounting::{Traffic, TrafficAccounting};
+use crate::audit::AuditLog;
+use crate::callout::PasswordCallout;
+use crate::config::Config;
+use crate::connection::ConnectionCounter;
+use crate::controls;
+use crate::dn;
+use crate::entry::Entry;
+use crate::error::LdapError;
+use crate::filter;
+use crate::log;
+use crate::monitor::{Monitor, Snapshot, MONITOR_DN};
+use crate::password::constant_time_eq;
+use crate::queue::OperationQueue;
+use crate::session::Session;
+use crate::throttle::BindThrottle;
+
+pub struct LdapController {
+    config: Config,
+    queue: OperationQueue,
+    callouts: Vec<PasswordCallout>,
+    throttle: BindThrottle,
+    audit: Option<AuditLog>,
+    access: Option<AccessLog>,
+    accounting: TrafficAccounting,
+    connections: ConnectionCounter,
+    monitor: Monitor,
+}
+
+impl LdapController {
+    pub fn new(config: Config) -> io::Result<Self> {
+        let queue = OperationQueue::new(config.max_operations, config.operation_wait);
+        let callouts = config
+            .password_callouts
+            .iter()
+            .map(|c| PasswordCallout::new(c, config.callout_timeout, config.callout_cache_ttl))
+            .collect();
+        let throttle = BindThrottle::new(
+            config.bind_max_failures,
+            config.bind_failure_window,
+            config.bind_lockout,
+        );
+
+        let audit = config
+            .audit_log
+            .as_deref()
+            .map(AuditLog::open)
+            .transpose()?;
+        let access = config
+            .access_log
+            .as_deref()
+            .map(AccessLog::open)
+            .transpose()?;
+        let accounting = TrafficAccounting::new(config.hourly_quota);
+        let connections = ConnectionCounter::new(config.max_connections);
+        let monitor = Monitor::new(SystemTime::now());
+
+        Ok(LdapController {
+            config,
+            queue,
+            callouts,
+            throttle,
+            audit,
+            access,
+            accounting,
+            connections,
+            monitor,
+        })
+    }
+
+    pub fn handle_ldap_message(&self, session: &mut Session, msg: LdapMessage) -> Vec<LdapMessage> {
+        let res = self.process(session, &msg);
+
+        // recorded whichever way the operation ended, even if it never ran
+        if let Some(audit) = &self.audit {
+            audit.record(session, &msg.protocol_op, res.last());
+        }
+
+        res
+    }
+
+    fn process(&self, session: &mut Session, msg: &LdapMessage) -> Vec<LdapMessage> {
+        let controls = msg.controls.as_deref().unwrap_or_default();
+        if let Err(e) = controls::check_controls(&msg.protocol_op, controls) {
+            return e
+                .into_response(msg.message_id, &msg.protocol_op)
+                .into_iter()
+                .collect();
+        }
+
+        let Some(_permit) = self.queue.acquire() else {
+            return LdapError::new(ResultCode::Busy, "too many operations in progress")
+                .into_response(msg.message_id, &msg.protocol_op)
+                .into_iter()
+                .collect();
+        };
+
+        // binding is always allowed, though it can't escape a spent quota
+        let quota = match &msg.protocol_op {
+            ProtocolOp::BindRequest(_) => Ok(()),
+            _ => self.accounting.check_quota(session),
+        };
+
+        // a panic while handling one operation fails that operation, rather
+        // than taking the client's connection down with it
+        let res = quota.and_then(|()| {
+            let handled = panic::catch_unwind(AssertUnwindSafe(|| {
+                self.dispatch(session, msg.message_id, &msg.protocol_op)
+            }));
+            handled.unwrap_or_else(|_| {
+                log::error!("request handler panicked");
+                Err(LdapError::new(
+                    ResultCode::Other,
+                    "internal error handling request",
+                ))
+            })
+        });
+
+        match res {
+            Ok(res) => res,
+            Err(e) => e
+                .into_response(msg.message_id, &msg.protocol_op)
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn access_log(&self) -> Option<&AccessLog> {
+        self.access.as_ref()
+    }
+
+    pub fn connections(&self) -> &ConnectionCounter {
+        &self.connections
+    }
+
+    pub fn monitor(&self) -> &Monitor {
+        &self.monitor
+    }
+
+    pub fn record_traffic(&self, session: &mut Session, traffic: Traffic) {
+        self.accounting.record(session, traffic);
+    }
+
+    fn dispatch(
+        &self,
+        session: &mut Session,
+        msg_id: MessageId,
+        op: &ProtocolOp,
+    ) -> Result<Vec<LdapMessage>, LdapError> {
+        match op {
+            ProtocolOp::BindRequest(req) => self
+                .handle_bind_request(session, msg_id, req)
+                .map(|res| vec![res]),
+            ProtocolOp::SearchRequest(req) => self.handle_search_request(msg_id, req),
+            // no extended operations are recognised (RFC 4511 section 4.12)
+            ProtocolOp::ExtendedReq(req) => Err(LdapError::new(
+                ResultCode::ProtocolError,
+                format!(
+                    "unrecognised extended operation {}",
+                    String::from_utf8_lossy(&req.request_name)
+                ),
+            )),
+            _ => Err(LdapError::new(
+                ResultCode::UnwillingToPerform,
+                "operation not supported",
+            )),
+        }
+    }
+
+    fn handle_bind_request(
+        &self,
+        session: &mut Session,
+        msg_id: MessageId,
+        req: &BindRequest,
+    ) -> Result<LdapMessage, LdapError> {
+        let name = String::from_utf8_lossy(&req.name);
+
+        // whatever the outcome, the connection is anonymous until the bind succeeds
+        session.bind_dn = None;
+
+        session.check_bind_policy(req)?;
+        dn::parse(&name)?;
+
+        // a name with an empty password is an unauthenticated bind, which must
+        // never be mistaken for a successful authentication as that name
+        if matches!(&req.authentication, AuthenticationChoice::Simple(pw) if pw.is_empty()) {
+            return Ok(bind_success(msg_id, req, "bound anonymously"));
+        }
+
+        let ip = session.peer.ip();
+        self.throttle.check(ip, &name)?;
+
+        match self.authenticate(&name, req) {
+            Ok(diagnostic) => {
+                self.throttle.record_success(&name);
+                session.bind_dn = Some(name.to_string());
+                Ok(bind_success(msg_id, req, diagnostic))
+            }
+            Err(e) => {
+                if e.code == ResultCode::InvalidCredentials {
+                    self.throttle.record_failure(ip, &name);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Searches the monitor subtree, the only entries the server has so far
+    fn handle_search_request(
+        &self,
+        msg_id: MessageId,
+        req: &SearchRequest,
+    ) -> Result<Vec<LdapMessage>, LdapError> {
+        let base = String::from_utf8_lossy(&req.base_object);
+        dn::parse(&base)?;
+
+        let snapshot = Snapshot {
+            connections_total: self.connections.total(),
+            connections_current: self.connections.open(),
+            executing: self.queue.in_flight(),
+            queued: self.queue.waiting(),
+        };
+        let entries = match dn::is_within(&base, MONITOR_DN) {
+            true => self.monitor.entries(snapshot),
+            false => Vec::new(),
+        };
+
+        if !entries.iter().any(|e| dn::is_same_dn(&e.dn, &base)) {
+            return Err(LdapError::new(ResultCode::NoSuchObject, ""));
+        }
+
+        let in_scope = |e: &Entry| match req.scope {
+            SearchRequestScope::BaseObject => dn::is_same_dn(&e.dn, &base),
+            SearchRequestScope::SingleLevel => {
+                dn::parent(&e.dn).is_some_and(|parent| dn::is_same_dn(parent, &base))
+            }
+            _ => dn::is_within(&e.dn, &base),
+        };
+
+        // the entries are the whole subtree, so each one's children are among them
+        let parents: Vec<_> = entries
+            .iter()
+            .filter_map(|e| dn::parent(&e.dn).map(dn::normalise))
+            .collect();
+
+        // limits of zero from the client mean it has none of its own
+        let size_limit = match req.size_limit {
+            0 => self.config.search_size_limit,
+            n => Some(
+                self.config
+                    .search_size_limit
+                    .unwrap_or(usize::MAX)
+                    .min(n as usize),
+            ),
+        };
+        let time_limit = match req.time_limit {
+            0 => self.config.search_time_limit,
+            n => Some(
+                self.config
+                    .search_time_limit
+                    .unwrap_or(Duration::MAX)
+                    .min(Duration::from_secs(n.into())),
+            ),
+        };
+        let deadline = time_limit.and_then(|limit| Instant::now().checked_add(limit));
+
+        // entries found before a limit is reached are still returned
+        let mut res = Vec::new();
+        let mut code = ResultCode::Success;
+        let mut diagnostic = "";
+        let mut returned_bytes = 0;
+        for e in entries {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                code = ResultCode::TimeLimitExceeded;
+                break;
+            }
+
+            let dn = e.dn.clone();
+            let normalised = dn::normalise(&dn);
+            let children = parents.iter().filter(|p| **p == normalised).count();
+            let e = e
+                .with_operational("entryDN", dn)
+                .with_operational(
+                    "hasSubordinates",
+                    if children > 0 { "TRUE" } else { "FALSE" },
+                )
+                .with_operational("numSubordinates", children);
+
+            if !in_scope(&e) || filter::matches(&req.filter, &e) != Some(true) {
+                continue;
+            }
+            if size_limit.is_some_and(|limit| res.len() >= limit) {
+                code = ResultCode::SizeLimitExceeded;
+                break;
+            }
+
+            let entry = e.into_search_result(msg_id, &req.attributes, req.types_only);
+            if let Some(max) = self.config.search_max_bytes {
+                returned_bytes += rasn::ber::encode(&entry).map_or(0, |ber| ber.len() as u64);
+                if returned_bytes > max {
+                    code = ResultCode::AdminLimitExceeded;
+                    diagnostic = "search returned too much data";
+                    break;
+                }
+            }
+            res.push(entry);
+        }
+
+        res.push(LdapMessage::new(
+            msg_id,
+            ProtocolOp::SearchResDone(SearchResultDone(LdapResult::new(
+                code,
+                "".into(),
+                diagnostic.into(),
+            ))),
+        ));
+
+        Ok(res)
+    }
+
+    /// Checks the credentials in a bind request, returning the diagnostic
+    /// message for a successful bind
+    fn authenticate(&self, name: &str, req: &BindRequest) -> Result<&'static str, LdapError> {
+        if let Some(root) = &self.config.root {
+            if dn::is_same_dn(name, &root.dn) {
+                return match &req.authentication {
+                    AuthenticationChoice::Simple(pw)
+                        if constant_time_eq(pw, root.password.as_bytes()) =>
+                    {
+                        Ok("bound as root")
+                    }
+                    _ => Err(LdapError::new(ResultCode::InvalidCredentials, "")),
+                };
+            }
+        }
+
+        if let Some(callout) = self.callouts.iter().find(|c| c.covers(name)) {
+            return match &req.authentication {
+                AuthenticationChoice::Simple(pw) if callout.verify(name, pw)? => {
+                    Ok("password verified by callout")
+                }
+                AuthenticationChoice::Simple(_) => {
+                    Err(LdapError::new(ResultCode::InvalidCredentials, ""))
+                }
+                _ => Err(LdapError::new(
+                    ResultCode::AuthMethodNotSupported,
+                    "only simple binds are supported for this subtree",
+                )),
+            };
+        }
+
+        // there are no entries with passwords to check anything else against,
+        // and a bind as a DN with no entry fails (RFC 4513 section 5.1.3)
+        match &req.authentication {
+            AuthenticationChoice::Simple(_) => {
+                Err(LdapError::new(ResultCode::InvalidCredentials, ""))
+            }
+            _ => Err(LdapError::new(
+                ResultCode::AuthMethodNotSupported,
+                "only simple binds are supported",
+            )),
+        }
+    }
+}
+
+fn bind_success(msg_id: MessageId, req: &BindRequest, diagnostic: &'static str) -> LdapMessage {
+    LdapMessage::new(
+        msg_id,
+        ProtocolOp::BindResponse(BindResponse::new(
+            ResultCode::Success,
+            req.name.clone(),
+            diagnostic.into(),
+            None,
+            None,
+        )),
+    )
+}
@@ -0,0 +1,118 @@
+use rasn_ldap::{Control, ProtocolOp, ResultCode};
+
+use crate::error::LdapError;
+
+/// ManageDsaIT (RFC 3296). There are no referral objects to manage yet so
+/// honouring it is a no-op, but clients commonly send it marked critical.
+const MANAGE_DSA_IT_OID: &str = "2.16.840.1.113730.3.4.2";
+
+/// A control the server recognises, and the operations it may be attached to
+struct SupportedControl {
+    oid: &'static str,
+    applies_to: fn(&ProtocolOp) -> bool,
+}
+
+const SUPPORTED_CONTROLS: &[SupportedControl] = &[SupportedControl {
+    oid: MANAGE_DSA_IT_OID,
+    applies_to: |op| {
+        matches!(
+            op,
+            ProtocolOp::SearchRequest(_)
+                | ProtocolOp::ModifyRequest(_)
+                | ProtocolOp::AddRequest(_)
+                | ProtocolOp::DelRequest(_)
+                | ProtocolOp::ModDnRequest(_)
+                | ProtocolOp::CompareRequest(_)
+        )
+    },
+}];
+
+/// Checks the controls attached to a request (RFC 4511 section 4.1.11).
+///
+/// Controls that are unrecognised or inappropriate for the operation are
+/// ignored, unless they are marked critical in which case the operation must
+/// not be performed at all.
+pub fn check_controls(op: &ProtocolOp, controls: &[Control]) -> Result<(), LdapError> {
+    match controls
+        .iter()
+        .enumerate()
+        .find(|(_, c)| c.criticality && !is_supported(op, c))
+    {
+        Some((i, c)) => {
+            let oid = String::from_utf8_lossy(&c.control_type);
+            Err(LdapError::new(
+                ResultCode::UnavailableCriticalExtension,
+                format!("critical control {oid} is not supported for this operation"),
+            )
+            .with_oid(oid)
+            .with_index(i)
+            .with_rule("criticality"))
+        }
+        None => Ok(()),
+    }
+}
+
+fn is_supported(op: &ProtocolOp, control: &Control) -> bool {
+    SUPPORTED_CONTROLS
+        .iter()
+        .any(|s| &control.control_type[..] == s.oid.as_bytes() && (s.applies_to)(op))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rasn_ldap::{AuthenticationChoice, BindRequest, DelRequest};
+
+    fn bind() -> ProtocolOp {
+        ProtocolOp::BindRequest(BindRequest::new(
+            3,
+            "".into(),
+            AuthenticationChoice::Simple("".into()),
+        ))
+    }
+
+    fn delete() -> ProtocolOp {
+        ProtocolOp::DelRequest(DelRequest("cn=a".into()))
+    }
+
+    fn control(oid: &str, critical: bool) -> Control {
+        Control::new(oid.as_bytes().to_vec().into(), critical, None)
+    }
+
+    #[test]
+    fn unknown_non_critical_controls_are_ignored() {
+        let controls = [control("1.2.3.4", false), control("1.2.3.5", false)];
+        assert!(check_controls(&delete(), &controls).is_ok());
+        assert!(check_controls(&bind(), &controls).is_ok());
+    }
+
+    #[test]
+    fn unknown_critical_controls_are_rejected() {
+        let err = check_controls(&delete(), &[control("1.2.3.4", true)]).unwrap_err();
+        assert_eq!(err.code, ResultCode::UnavailableCriticalExtension);
+    }
+
+    #[test]
+    fn controls_only_apply_to_some_operations() {
+        assert!(check_controls(&delete(), &[control(MANAGE_DSA_IT_OID, true)]).is_ok());
+
+        let err = check_controls(&bind(), &[control(MANAGE_DSA_IT_OID, true)]).unwrap_err();
+        assert_eq!(err.code, ResultCode::UnavailableCriticalExtension);
+        assert!(check_controls(&bind(), &[control(MANAGE_DSA_IT_OID, false)]).is_ok());
+    }
+
+    #[test]
+    fn diagnostic_names_the_control() {
+        let controls = [
+            control(MANAGE_DSA_IT_OID, true),
+            control("1.2.3.4", false),
+            control("1.2.3.5", true),
+        ];
+        let err = check_controls(&delete(), &controls).unwrap_err();
+        assert_eq!(
+            err.diagnostic_message(),
+            "[oid=1.2.3.5; index=2; rule=criticality] \
+             critical control 1.2.3.5 is not supported for this operation"
+        );
+    }
+}
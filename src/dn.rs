@@ -0,0 +1,401 @@
+use rasn_ldap::ResultCode;
+
+use crate::error::LdapError;
+
+/// Characters that must be escaped wherever they appear in a string value
+/// (RFC 4514 section 2.4)
+const SPECIAL: &[u8] = b"\"+,;<>\\";
+
+/// A relative distinguished name, one or more attribute values joined by `+`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rdn(Vec<AttributeTypeAndValue>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeTypeAndValue {
+    pub attr: String,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    String(String),
+    /// The BER encoding of a value given in `#hex` form, which without a
+    /// schema can't be decoded
+    Ber(Vec<u8>),
+}
+
+/// Parses a DN in its RFC 4514 string form, leaf RDN first.
+///
+/// Values may contain `\`-escaped special characters and hex pairs, or be
+/// given as `#` followed by hex. Spaces around the separators are ignored,
+/// and so are quotes around a value, which were allowed by RFC 2253.
+pub fn parse(dn: &str) -> Result<Vec<Rdn>, LdapError> {
+    let mut parser = Parser {
+        s: dn.as_bytes(),
+        pos: 0,
+    };
+
+    parser.skip_spaces();
+    if parser.peek().is_none() {
+        return Ok(Vec::new());
+    }
+
+    let mut rdns = vec![parser.rdn()?];
+    while parser.peek() == Some(b',') {
+        parser.pos += 1;
+        rdns.push(parser.rdn()?);
+    }
+
+    match parser.peek() {
+        None => Ok(rdns),
+        Some(c) => Err(parser.error(format!("unexpected '{}'", c as char))),
+    }
+}
+
+/// Normalises a DN for comparison, putting it in a canonical form where
+/// attribute types and values are lowercased, only the characters that must
+/// be escaped are, and the values of a multi-valued RDN are sorted.
+///
+/// A DN that doesn't parse is only trimmed and lowercased, so it can still be
+/// used as a key but will never be the same as a valid DN.
+pub fn normalise(dn: &str) -> String {
+    match canonical_rdns(dn) {
+        Some(rdns) => rdns.join(","),
+        None => dn.trim().to_lowercase(),
+    }
+}
+
+pub fn is_same_dn(a: &str, b: &str) -> bool {
+    normalise(a) == normalise(b)
+}
+
+/// The DN of an entry's parent, or `None` for a DN with a single RDN
+pub fn parent(dn: &str) -> Option<&str> {
+    let mut parser = Parser {
+        s: dn.as_bytes(),
+        pos: 0,
+    };
+
+    parser.rdn().ok()?;
+    match parser.peek() {
+        Some(b',') => Some(&dn[parser.pos + 1..]),
+        _ => None,
+    }
+}
+
+/// Whether `dn` is `base` or one of its descendants
+pub fn is_within(dn: &str, base: &str) -> bool {
+    match (canonical_rdns(dn), canonical_rdns(base)) {
+        (Some(dn), Some(base)) => dn.ends_with(&base),
+        _ => false,
+    }
+}
+
+fn canonical_rdns(dn: &str) -> Option<Vec<String>> {
+    let rdns = parse(dn).ok()?;
+    Some(rdns.iter().map(Rdn::canonical).collect())
+}
+
+impl Rdn {
+    fn canonical(&self) -> String {
+        let mut avas: Vec<_> = self.0.iter().map(|ava| ava.canonical()).collect();
+        avas.sort();
+        avas.join("+")
+    }
+}
+
+impl AttributeTypeAndValue {
+    fn canonical(&self) -> String {
+        let value = match &self.value {
+            Value::String(s) => escape(&s.to_lowercase()),
+            Value::Ber(ber) => format!("#{}", hex::encode(ber)),
+        };
+
+        format!("{}={value}", self.attr.to_lowercase())
+    }
+}
+
+/// Escapes a value for the string form of a DN (RFC 4514 section 2.4)
+fn escape(value: &str) -> String {
+    let last = value.len().saturating_sub(1);
+
+    let mut out = String::with_capacity(value.len());
+    for (i, c) in value.char_indices() {
+        match c {
+            '\0' => out.push_str("\\00"),
+            c if c.is_ascii() && SPECIAL.contains(&(c as u8)) => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' ' | '#' if i == 0 => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' ' if i == last => out.push_str("\\ "),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek() == Some(b' ') {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, reason: impl Into<String>) -> LdapError {
+        LdapError::new(
+            ResultCode::InvalidDnSyntax,
+            format!("invalid DN at position {}: {}", self.pos, reason.into()),
+        )
+    }
+
+    fn rdn(&mut self) -> Result<Rdn, LdapError> {
+        let mut avas = vec![self.attribute_type_and_value()?];
+        while self.peek() == Some(b'+') {
+            self.pos += 1;
+            avas.push(self.attribute_type_and_value()?);
+        }
+
+        Ok(Rdn(avas))
+    }
+
+    fn attribute_type_and_value(&mut self) -> Result<AttributeTypeAndValue, LdapError> {
+        self.skip_spaces();
+        let attr = self.attribute_type()?;
+
+        self.skip_spaces();
+        if self.peek() != Some(b'=') {
+            return Err(self.error(format!("expected '=' after {attr}")));
+        }
+        self.pos += 1;
+        self.skip_spaces();
+
+        let value = match self.peek() {
+            Some(b'#') => Value::Ber(self.hex_value()?),
+            Some(b'"') => Value::String(self.quoted_value()?),
+            _ => Value::String(self.string_value()?),
+        };
+        self.skip_spaces();
+
+        Ok(AttributeTypeAndValue { attr, value })
+    }
+
+    /// Reads either a name (`cn`) or a numeric OID (`2.5.4.3`)
+    fn attribute_type(&mut self) -> Result<String, LdapError> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'.')
+        {
+            self.pos += 1;
+        }
+        let attr = String::from_utf8_lossy(&self.s[start..self.pos]).into_owned();
+
+        let valid = match attr.as_bytes().first() {
+            Some(c) if c.is_ascii_alphabetic() => !attr.contains('.'),
+            Some(c) if c.is_ascii_digit() => attr
+                .split('.')
+                .all(|n| !n.is_empty() && n.bytes().all(|c| c.is_ascii_digit())),
+            _ => false,
+        };
+
+        match valid {
+            true => Ok(attr),
+            false => Err(self.error("expected an attribute type")),
+        }
+    }
+
+    fn hex_value(&mut self) -> Result<Vec<u8>, LdapError> {
+        self.pos += 1;
+
+        let mut ber = Vec::new();
+        while let Some(b) = self.hex_pair() {
+            ber.push(b);
+        }
+
+        match ber.is_empty() {
+            true => Err(self.error("expected hex pairs after '#'")),
+            false => Ok(ber),
+        }
+    }
+
+    fn quoted_value(&mut self) -> Result<String, LdapError> {
+        self.pos += 1;
+
+        let mut value = Vec::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error("unterminated quoted value")),
+                Some(b'"') => break,
+                Some(b'\\') => value.push(self.escaped()?),
+                Some(c) => {
+                    value.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+        self.pos += 1;
+
+        self.utf8(value)
+    }
+
+    fn string_value(&mut self) -> Result<String, LdapError> {
+        let mut value = Vec::new();
+        // unescaped trailing spaces aren't part of the value, escaped ones are
+        let mut significant = 0;
+
+        loop {
+            match self.peek() {
+                None | Some(b',') | Some(b'+') => break,
+                Some(b'\\') => {
+                    value.push(self.escaped()?);
+                    significant = value.len();
+                }
+                Some(c @ (b'"' | b';' | b'<' | b'>')) => {
+                    return Err(self.error(format!("'{}' must be escaped", c as char)));
+                }
+                Some(c) => {
+                    value.push(c);
+                    self.pos += 1;
+                    if c != b' ' {
+                        significant = value.len();
+                    }
+                }
+            }
+        }
+
+        value.truncate(significant);
+        self.utf8(value)
+    }
+
+    /// Hex pairs can spell out any bytes, but a string value must be UTF-8
+    fn utf8(&self, value: Vec<u8>) -> Result<String, LdapError> {
+        String::from_utf8(value).map_err(|_| self.error("value is not valid UTF-8"))
+    }
+
+    /// Reads a `\` escape, either of a special character or a hex pair
+    fn escaped(&mut self) -> Result<u8, LdapError> {
+        self.pos += 1;
+
+        if let Some(b) = self.hex_pair() {
+            return Ok(b);
+        }
+
+        match self.peek() {
+            Some(c) if c == b' ' || c == b'#' || c == b'=' || SPECIAL.contains(&c) => {
+                self.pos += 1;
+                Ok(c)
+            }
+            _ => Err(self.error("invalid escape")),
+        }
+    }
+
+    fn hex_pair(&mut self) -> Option<u8> {
+        let pair = self.s.get(self.pos..self.pos + 2)?;
+        if !pair.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+
+        self.pos += 2;
+        u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(dn: &str) -> Value {
+        parse(dn).unwrap().remove(0).0.remove(0).value
+    }
+
+    fn is_invalid(dn: &str) -> bool {
+        parse(dn).is_err_and(|e| e.code == ResultCode::InvalidDnSyntax)
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(value(r"cn=a\,b\+c\\"), Value::String(r"a,b+c\".to_string()));
+        assert_eq!(value(r"cn=\41\c3\a9"), Value::String("Aé".to_string()));
+        assert_eq!(normalise(r"CN=A\2cB"), r"cn=a\,b");
+        assert!(is_invalid(r"cn=a\x"));
+        assert!(is_invalid(r"cn=a\4"));
+        assert_eq!(value(r"cn=a\+1"), Value::String("a+1".to_string()));
+        assert!(is_invalid("cn=a;b"));
+        assert!(is_invalid("cn=a\""));
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        assert!(is_invalid(r"cn=\ff"));
+        assert!(is_invalid(r"cn=\c3"));
+        assert!(is_invalid(r#"cn="\fe""#));
+        assert!(!is_same_dn(r"cn=\ff", r"cn=\fe"));
+    }
+
+    #[test]
+    fn hex_values() {
+        assert_eq!(
+            value("cn=#04024869"),
+            Value::Ber(vec![0x04, 0x02, 0x48, 0x69])
+        );
+        assert_eq!(normalise("CN=#04024869"), "cn=#04024869");
+        assert!(is_invalid("cn=#"));
+        assert!(is_invalid("cn=#0"));
+        assert!(is_invalid("cn=#zz"));
+    }
+
+    #[test]
+    fn quoted_values() {
+        assert_eq!(value(r#"cn="a,b;c""#), Value::String("a,b;c".to_string()));
+        assert_eq!(normalise(r#"cn="a,b", dc=x"#), r"cn=a\,b,dc=x");
+        assert!(is_invalid(r#"cn="a"#));
+    }
+
+    #[test]
+    fn trailing_spaces() {
+        assert_eq!(value("cn=a  "), Value::String("a".to_string()));
+        assert_eq!(value(r"cn=a\ "), Value::String("a ".to_string()));
+        assert_eq!(value(r"cn=a \  "), Value::String("a  ".to_string()));
+        assert_eq!(normalise(r"cn=a\ , dc=x"), r"cn=a\ ,dc=x");
+        assert_eq!(normalise(r"cn=\ a"), r"cn=\ a");
+    }
+
+    #[test]
+    fn multi_valued_rdns() {
+        assert_eq!(normalise("sn=B+CN=a,dc=x"), "cn=a+sn=b,dc=x");
+        assert!(is_same_dn("cn=a+sn=b, dc=x", "SN=b + cn=A,DC=X"));
+        assert!(!is_same_dn("cn=a+sn=b,dc=x", "cn=a,sn=b,dc=x"));
+    }
+
+    #[test]
+    fn within() {
+        assert!(is_within("cn=a,dc=x,dc=y", "DC=X, dc=y"));
+        assert!(is_within("dc=x,dc=y", "dc=x,dc=y"));
+        assert!(is_within(r"cn=a\,dc=x,dc=y", "dc=y"));
+        assert!(!is_within(r"cn=a\,dc=x,dc=y", "dc=x,dc=y"));
+        assert!(!is_within("cn=a,dc=xdc=y", "dc=y"));
+        assert!(!is_within("dc=y", "cn=a,dc=y"));
+    }
+
+    #[test]
+    fn parents() {
+        assert_eq!(parent("cn=a,dc=x,dc=y"), Some("dc=x,dc=y"));
+        assert_eq!(parent(r"cn=a\,b,dc=x"), Some("dc=x"));
+        assert_eq!(parent(r#"cn="a,b",dc=x"#), Some("dc=x"));
+        assert_eq!(parent("dc=x"), None);
+    }
+}
@@ -0,0 +1,226 @@
+use rasn_ldap::{
+    AttributeDescription, AttributeSelection, LdapMessage, MessageId, PartialAttribute, ProtocolOp,
+    SearchResultEntry,
+};
+
+/// The OIDs of the attributes entries have that have standard ones, so that
+/// they can be asked for either way
+const ATTRIBUTE_OIDS: &[(&str, &str)] = &[
+    ("objectClass", "2.5.4.0"),
+    ("cn", "2.5.4.3"),
+    ("entryDN", "1.3.6.1.1.20"),
+    ("hasSubordinates", "2.5.18.9"),
+    ("numSubordinates", "1.3.6.1.4.1.453.16.2.103"),
+];
+
+/// A directory entry as returned by a search
+pub struct Entry {
+    pub dn: String,
+    pub attributes: Vec<Attribute>,
+}
+
+pub struct Attribute {
+    pub name: &'static str,
+    pub values: Vec<String>,
+    /// Operational attributes are only returned by a search when asked for
+    /// by name, though filters can always use them (RFC 4512 section 3.4)
+    pub operational: bool,
+}
+
+impl Entry {
+    pub fn new(dn: impl Into<String>) -> Self {
+        Entry {
+            dn: dn.into(),
+            attributes: Vec::new(),
+        }
+    }
+
+    pub fn with(self, name: &'static str, value: impl ToString) -> Self {
+        self.with_value(name, value.to_string(), false)
+    }
+
+    pub fn with_operational(self, name: &'static str, value: impl ToString) -> Self {
+        self.with_value(name, value.to_string(), true)
+    }
+
+    fn with_value(mut self, name: &'static str, value: String, operational: bool) -> Self {
+        match self.attributes.iter_mut().find(|a| a.name == name) {
+            Some(attr) => attr.values.push(value),
+            None => self.attributes.push(Attribute {
+                name,
+                values: vec![value],
+                operational,
+            }),
+        }
+        self
+    }
+
+    /// The values of an attribute, matching its name case insensitively
+    pub fn get(&self, name: &str) -> Option<&[String]> {
+        self.attributes
+            .iter()
+            .find(|a| a.is_named(name.as_bytes()))
+            .map(|a| &a.values[..])
+    }
+
+    /// Builds the search result for this entry, with the attributes that
+    /// `requested` selects, or only their names if `types_only` is set
+    pub fn into_search_result(
+        self,
+        msg_id: MessageId,
+        requested: &AttributeSelection,
+        types_only: bool,
+    ) -> LdapMessage {
+        let attributes = self
+            .attributes
+            .into_iter()
+            .filter_map(|a| {
+                let description = a.selected_as(requested)?;
+                let values = match types_only {
+                    true => Default::default(),
+                    false => a.values.into_iter().map(Into::into).collect(),
+                };
+                Some(PartialAttribute::new(description, values))
+            })
+            .collect();
+
+        LdapMessage::new(
+            msg_id,
+            ProtocolOp::SearchResEntry(SearchResultEntry::new(self.dn.into(), attributes)),
+        )
+    }
+}
+
+impl Attribute {
+    /// Whether `name` is this attribute's name, in any case, or its OID
+    fn is_named(&self, name: &[u8]) -> bool {
+        name.eq_ignore_ascii_case(self.name.as_bytes())
+            || ATTRIBUTE_OIDS
+                .iter()
+                .any(|(attr, oid)| *attr == self.name && name == oid.as_bytes())
+    }
+
+    /// The description to return this attribute under for a search asking for
+    /// `requested`, or `None` if it isn't returned. Besides names, the list may
+    /// hold `*` for all user attributes, `+` for all operational ones (RFC
+    /// 3673), or just `1.1` for none at all (RFC 4511). An empty list is the
+    /// same as `*`.
+    ///
+    /// An attribute asked for by name is described the way the client spelt
+    /// it, and one selected any other way by its own name.
+    fn selected_as(&self, requested: &AttributeSelection) -> Option<AttributeDescription> {
+        if let Some(name) = requested.iter().find(|r| self.is_named(r)) {
+            return Some(name.clone());
+        }
+
+        let has = |selector: &[u8]| requested.iter().any(|r| **r == *selector);
+        let selected = match self.operational {
+            true => has(b"+"),
+            false => requested.is_empty() || has(b"*"),
+        };
+        selected.then(|| self.name.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry::new("cn=Time,cn=Monitor")
+            .with("objectClass", "monitorContainer")
+            .with("cn", "Time")
+            .with_operational("entryDN", "cn=Time,cn=Monitor")
+            .with_operational("numSubordinates", 3)
+    }
+
+    /// The attributes returned for `requested`, with their values
+    fn returned(requested: &[&str], types_only: bool) -> Vec<(String, Vec<String>)> {
+        let requested = requested
+            .iter()
+            .map(|r| r.as_bytes().to_vec().into())
+            .collect();
+        let ProtocolOp::SearchResEntry(res) = entry()
+            .into_search_result(1, &requested, types_only)
+            .protocol_op
+        else {
+            panic!("not a search result entry");
+        };
+
+        res.attributes
+            .into_iter()
+            .map(|a| {
+                let values = a
+                    .vals
+                    .iter()
+                    .map(|v| String::from_utf8_lossy(v).into())
+                    .collect();
+                (String::from_utf8_lossy(&a.r#type).into(), values)
+            })
+            .collect()
+    }
+
+    fn names(requested: &[&str]) -> Vec<String> {
+        returned(requested, false)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    #[test]
+    fn selectors() {
+        assert_eq!(names(&[]), ["objectClass", "cn"]);
+        assert_eq!(names(&["*"]), ["objectClass", "cn"]);
+        assert_eq!(names(&["+"]), ["entryDN", "numSubordinates"]);
+        assert_eq!(
+            names(&["*", "+"]),
+            ["objectClass", "cn", "entryDN", "numSubordinates"]
+        );
+        assert!(names(&["1.1"]).is_empty());
+        assert_eq!(names(&["1.1", "cn"]), ["cn"]);
+    }
+
+    #[test]
+    fn operational_attributes_by_name() {
+        assert_eq!(names(&["numSubordinates"]), ["numSubordinates"]);
+        assert_eq!(names(&["*", "entryDN"]), ["objectClass", "cn", "entryDN"]);
+        assert_eq!(names(&["cn"]), ["cn"]);
+    }
+
+    #[test]
+    fn oids() {
+        assert_eq!(names(&["2.5.4.3"]), ["2.5.4.3"]);
+        assert_eq!(
+            names(&["1.3.6.1.1.20", "2.5.4.0"]),
+            ["2.5.4.0", "1.3.6.1.1.20"]
+        );
+        assert!(names(&["2.5.4.4"]).is_empty());
+    }
+
+    #[test]
+    fn names_as_the_client_spelt_them() {
+        assert_eq!(names(&["CN", "objectclass"]), ["objectclass", "CN"]);
+        assert_eq!(
+            names(&["*", "NUMsubordinates"]),
+            ["objectClass", "cn", "NUMsubordinates"]
+        );
+    }
+
+    #[test]
+    fn types_only() {
+        assert_eq!(
+            returned(&["cn", "numSubordinates"], false),
+            [
+                ("cn".to_string(), vec!["Time".to_string()]),
+                ("numSubordinates".to_string(), vec!["3".to_string()])
+            ]
+        );
+        assert_eq!(
+            returned(&["cn", "numSubordinates"], true),
+            [
+                ("cn".to_string(), vec![]),
+                ("numSubordinates".to_string(), vec![])
+            ]
+        );
+    }
+}
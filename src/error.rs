@@ -0,0 +1,116 @@
+use rasn_ldap::{
+    AddResponse, BindResponse, CompareResponse, DelResponse, ExtendedResponse, LdapMessage,
+    LdapResult, MessageId, ModifyDnResponse, ModifyResponse, ProtocolOp, ResultCode,
+    SearchResultDone,
+};
+
+#[derive(Debug)]
+pub struct LdapError {
+    pub code: ResultCode,
+    pub message: String,
+    pub diagnostic: Diagnostic,
+}
+
+/// Machine readable details of what caused an error.
+///
+/// These are rendered ahead of the human readable message in a stable
+/// `[key=value; ...]` form so that client tooling can pick them apart, e.g.
+/// `[oid=2.5.4.3; index=1; rule=caseIgnoreMatch] duplicate value`. Keys that
+/// have no value are left out, and the brackets are omitted entirely when
+/// there are no details at all. The OID usually comes from the client, so it
+/// is left out too unless it is a well-formed numeric OID, which can't
+/// contain the delimiters.
+#[derive(Debug, Default)]
+pub struct Diagnostic {
+    /// The OID of the offending attribute type or control
+    pub oid: Option<String>,
+    /// The position of the offending value within its attribute or sequence
+    pub index: Option<usize>,
+    /// The name of the rule that was violated
+    pub rule: Option<&'static str>,
+}
+
+impl LdapError {
+    pub fn new(code: ResultCode, message: impl Into<String>) -> Self {
+        LdapError {
+            code,
+            message: message.into(),
+            diagnostic: Diagnostic::default(),
+        }
+    }
+
+    pub fn with_oid(mut self, oid: impl Into<String>) -> Self {
+        self.diagnostic.oid = Some(oid.into());
+        self
+    }
+
+    pub fn with_index(mut self, index: usize) -> Self {
+        self.diagnostic.index = Some(index);
+        self
+    }
+
+    pub fn with_rule(mut self, rule: &'static str) -> Self {
+        self.diagnostic.rule = Some(rule);
+        self
+    }
+
+    /// The diagnostic message sent to the client, see [`Diagnostic`]
+    pub fn diagnostic_message(&self) -> String {
+        let d = &self.diagnostic;
+        let details: Vec<String> = [
+            d.oid
+                .as_ref()
+                .filter(|oid| is_numeric_oid(oid))
+                .map(|oid| format!("oid={oid}")),
+            d.index.map(|index| format!("index={index}")),
+            d.rule.map(|rule| format!("rule={rule}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        if details.is_empty() {
+            self.message.clone()
+        } else {
+            format!("[{}] {}", details.join("; "), self.message)
+        }
+    }
+
+    /// Builds the response to `op` that reports this error, or `None` if `op`
+    /// is a request that never gets a response (unbind, abandon)
+    pub fn into_response(self, msg_id: MessageId, op: &ProtocolOp) -> Option<LdapMessage> {
+        let res = LdapResult::new(self.code, "".into(), self.diagnostic_message().into());
+
+        let op = match op {
+            ProtocolOp::BindRequest(_) => ProtocolOp::BindResponse(BindResponse::new(
+                res.result_code,
+                res.matched_dn,
+                res.diagnostic_message,
+                None,
+                None,
+            )),
+            ProtocolOp::SearchRequest(_) => ProtocolOp::SearchResDone(SearchResultDone(res)),
+            ProtocolOp::ModifyRequest(_) => ProtocolOp::ModifyResponse(ModifyResponse(res)),
+            ProtocolOp::AddRequest(_) => ProtocolOp::AddResponse(AddResponse(res)),
+            ProtocolOp::DelRequest(_) => ProtocolOp::DelResponse(DelResponse(res)),
+            ProtocolOp::ModDnRequest(_) => ProtocolOp::ModDnResponse(ModifyDnResponse(res)),
+            ProtocolOp::CompareRequest(_) => ProtocolOp::CompareResponse(CompareResponse(res)),
+            ProtocolOp::ExtendedReq(_) => ProtocolOp::ExtendedResp(ExtendedResponse {
+                result_code: res.result_code,
+                matched_dn: res.matched_dn,
+                diagnostic_message: res.diagnostic_message,
+                referral: None,
+                response_name: None,
+                response_value: None,
+            }),
+            _ => return None,
+        };
+
+        Some(LdapMessage::new(msg_id, op))
+    }
+}
+
+fn is_numeric_oid(oid: &str) -> bool {
+    oid.split('.')
+        .all(|arc| !arc.is_empty() && arc.bytes().all(|c| c.is_ascii_digit()))
+}
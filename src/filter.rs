@@ -0,0 +1,117 @@
+use std::cmp::Ordering;
+
+use rasn_ldap::{AttributeValueAssertion, Filter, SubstringChoice};
+
+use crate::dn;
+use crate::entry::Entry;
+
+/// Attributes whose values are DNs, and so are equal when they name the same
+/// entry rather than when they are spelt the same
+const DN_ATTRIBUTES: &[&str] = &["entryDN"];
+
+/// Evaluates a search filter against an entry, returning `None` where RFC 4511
+/// section 4.5.1.7 says the result is undefined.
+///
+/// There is no schema to take matching rules from, so values are compared
+/// case insensitively, and ordered as integers when both sides are integers.
+/// For the same reason every attribute type is taken to be recognised, so an
+/// assertion about an attribute the entry doesn't have is false.
+pub fn matches(filter: &Filter, entry: &Entry) -> Option<bool> {
+    match filter {
+        Filter::And(filters) => {
+            let mut result = Some(true);
+            for f in filters {
+                match matches(f, entry) {
+                    Some(false) => return Some(false),
+                    Some(true) => {}
+                    None => result = None,
+                }
+            }
+            result
+        }
+        Filter::Or(filters) => {
+            let mut result = Some(false);
+            for f in filters {
+                match matches(f, entry) {
+                    Some(true) => return Some(true),
+                    Some(false) => {}
+                    None => result = None,
+                }
+            }
+            result
+        }
+        Filter::Not(filter) => matches(filter, entry).map(|m| !m),
+        Filter::EqualityMatch(ava) | Filter::ApproxMatch(ava) if is_dn_attribute(ava) => {
+            let assertion = String::from_utf8_lossy(&ava.assertion_value);
+            let values = entry
+                .get(&String::from_utf8_lossy(&ava.attribute_desc))
+                .unwrap_or(&[]);
+            Some(values.iter().any(|v| dn::is_same_dn(v, &assertion)))
+        }
+        Filter::EqualityMatch(ava) | Filter::ApproxMatch(ava) => {
+            compare(entry, ava, |o| o == Ordering::Equal)
+        }
+        Filter::GreaterOrEqual(ava) => compare(entry, ava, |o| o != Ordering::Less),
+        Filter::LessOrEqual(ava) => compare(entry, ava, |o| o != Ordering::Greater),
+        Filter::Present(attr) => Some(entry.get(&String::from_utf8_lossy(attr)).is_some()),
+        Filter::Substrings(sub) => {
+            let values = entry
+                .get(&String::from_utf8_lossy(&sub.r#type))
+                .unwrap_or(&[]);
+            Some(values.iter().any(|v| substrings_match(v, &sub.substrings)))
+        }
+        // extensible matching needs matching rules, which don't exist yet
+        _ => None,
+    }
+}
+
+fn is_dn_attribute(ava: &AttributeValueAssertion) -> bool {
+    DN_ATTRIBUTES
+        .iter()
+        .any(|a| a.as_bytes().eq_ignore_ascii_case(&ava.attribute_desc))
+}
+
+fn compare(entry: &Entry, ava: &AttributeValueAssertion, f: fn(Ordering) -> bool) -> Option<bool> {
+    let assertion = String::from_utf8_lossy(&ava.assertion_value);
+    let values = entry
+        .get(&String::from_utf8_lossy(&ava.attribute_desc))
+        .unwrap_or(&[]);
+
+    Some(values.iter().any(|v| f(order(v, &assertion))))
+}
+
+fn order(value: &str, assertion: &str) -> Ordering {
+    match (value.parse::<i64>(), assertion.parse::<i64>()) {
+        (Ok(v), Ok(a)) => v.cmp(&a),
+        _ => value.to_lowercase().cmp(&assertion.to_lowercase()),
+    }
+}
+
+fn substrings_match(value: &str, substrings: &[SubstringChoice]) -> bool {
+    let value = value.to_lowercase();
+    let mut rest = &value[..];
+
+    for choice in substrings {
+        match choice {
+            SubstringChoice::Initial(s) => {
+                let Some(r) = rest.strip_prefix(&*String::from_utf8_lossy(s).to_lowercase()) else {
+                    return false;
+                };
+                rest = r;
+            }
+            SubstringChoice::Any(s) => {
+                let s = String::from_utf8_lossy(s).to_lowercase();
+                let Some(i) = rest.find(&s) else {
+                    return false;
+                };
+                rest = &rest[i + s.len()..];
+            }
+            SubstringChoice::Final(s) => {
+                return rest.ends_with(&*String::from_utf8_lossy(s).to_lowercase());
+            }
+            _ => return false,
+        }
+    }
+
+    true
+}
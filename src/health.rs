@@ -0,0 +1,98 @@
+use std::io::{Read, Result, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::controller::LdapController;
+use crate::log;
+
+/// How long a probe has to send its request before it's given up on, so a
+/// stalled client can't hold up the probes behind it
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Answers liveness and readiness probes over plain HTTP, e.g. from
+/// Kubernetes. `GET /livez` succeeds for as long as the server is running,
+/// and `GET /readyz` only while it is able to take new LDAP clients. A bare
+/// TCP connection to the health port also works as a liveness probe.
+pub struct Health {
+    listeners: usize,
+    accepting: AtomicUsize,
+}
+
+/// Marks a listener as accepting connections until dropped
+pub struct AcceptingGuard<'a>(&'a Health);
+
+impl Health {
+    pub fn new(listeners: usize) -> Self {
+        Health {
+            listeners,
+            accepting: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn accepting(&self) -> AcceptingGuard<'_> {
+        self.accepting.fetch_add(1, Ordering::SeqCst);
+        AcceptingGuard(self)
+    }
+
+    pub fn serve(&self, listener: TcpListener, controller: &LdapController) {
+        for stream in listener.incoming() {
+            let res = stream.and_then(|stream| self.answer_probe(stream, controller));
+            if let Err(e) = res {
+                log::debug!("failed to answer health probe: {e}");
+            }
+        }
+    }
+
+    fn answer_probe(&self, mut stream: TcpStream, controller: &LdapController) -> Result<()> {
+        stream.set_read_timeout(Some(PROBE_TIMEOUT))?;
+
+        // only the request line matters, and it fits in the first read
+        let mut buf = [0; 1024];
+        let n = stream.read(&mut buf)?;
+        let request = String::from_utf8_lossy(&buf[..n]);
+        let path = request.split_whitespace().nth(1).unwrap_or_default();
+
+        let (status, body) = match path {
+            "/livez" => ("200 OK", "ok".to_string()),
+            "/readyz" => match self.readiness(controller) {
+                Ok(()) => ("200 OK", "ready".to_string()),
+                Err(reason) => ("503 Service Unavailable", reason),
+            },
+            _ => ("404 Not Found", "not found".to_string()),
+        };
+
+        write!(
+            stream,
+            "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    fn readiness(&self, controller: &LdapController) -> std::result::Result<(), String> {
+        let accepting = self.accepting.load(Ordering::SeqCst);
+        if accepting < self.listeners {
+            return Err(format!(
+                "only {accepting} of {} listeners are accepting connections",
+                self.listeners
+            ));
+        }
+
+        let open = controller.connections().open();
+        if controller
+            .config()
+            .max_connections
+            .is_some_and(|max| open >= max)
+        {
+            return Err(format!("at the limit of {open} connections"));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for AcceptingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.accepting.fetch_sub(1, Ordering::SeqCst);
+    }
+}
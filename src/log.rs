@@ -0,0 +1,152 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+
+/// How much the server says about what it is doing, from least to most verbose
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            _ => Err(()),
+        }
+    }
+}
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+thread_local! {
+    /// The fields of the spans the current thread is in, outermost first
+    static SPANS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Sets the most verbose level that is logged, `Info` until this is called
+pub fn set_max_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Enters a span, e.g. `conn=3 peer=127.0.0.1:50312`, whose fields are added
+/// to every line logged by the current thread until the guard is dropped
+pub fn span(fields: String) -> Span {
+    SPANS.with(|spans| spans.borrow_mut().push(fields));
+    Span(PhantomData)
+}
+
+/// Leaves its span when dropped. Spans are per thread, so this can't be sent
+/// to another one.
+pub struct Span(PhantomData<*const ()>);
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        SPANS.with(|spans| spans.borrow_mut().pop());
+    }
+}
+
+/// Writes a line to stderr, use the level macros rather than calling this
+pub fn write(level: Level, args: fmt::Arguments) {
+    let mut line = format!(
+        "{} {:<5}",
+        format_timestamp(SystemTime::now()),
+        level.name()
+    );
+    SPANS.with(|spans| {
+        for fields in spans.borrow().iter() {
+            line.push(' ');
+            line.push_str(fields);
+        }
+    });
+    line.push_str(&format!(" {args}\n"));
+
+    let _ = io::stderr().lock().write_all(line.as_bytes());
+}
+
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($level) {
+            $crate::log::write($level, format_args!($($arg)+))
+        }
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)+) => { $crate::log::log!($crate::log::Level::Error, $($arg)+) };
+}
+
+// named so as not to be confused with the built-in `warn` attribute, and
+// exported as `log::warn` below
+macro_rules! warning {
+    ($($arg:tt)+) => { $crate::log::log!($crate::log::Level::Warn, $($arg)+) };
+}
+
+macro_rules! info {
+    ($($arg:tt)+) => { $crate::log::log!($crate::log::Level::Info, $($arg)+) };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => { $crate::log::log!($crate::log::Level::Debug, $($arg)+) };
+}
+
+pub(crate) use {debug, error, info, log, warning as warn};
+
+/// Escapes a client supplied value for a log line, so that it can't end its
+/// field or the line early and forge another. Control characters, `special`
+/// characters and bytes that are not UTF-8 are written as `\\xx` hex escapes.
+pub fn escape(value: &[u8], special: &[char]) -> String {
+    let needs_escape = |c: char| special.contains(&c) || c.is_control();
+
+    value
+        .utf8_chunks()
+        .flat_map(|chunk| {
+            let valid = chunk.valid().chars().map(move |c| match needs_escape(c) {
+                true => c
+                    .to_string()
+                    .bytes()
+                    .map(|b| format!("\\{b:02x}"))
+                    .collect(),
+                false => c.to_string(),
+            });
+            let invalid = chunk.invalid().iter().map(|b| format!("\\{b:02x}"));
+            valid.chain(invalid)
+        })
+        .collect()
+}
+
+/// Formats a time as an RFC 3339 UTC timestamp, e.g. `2024-06-01T09:30:00Z`
+pub fn format_timestamp(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string()
+}
@@ -1,46 +1,117 @@
-use std::io::{Read, Result, Write};
+use std::env;
+use std::io::{Error, ErrorKind, Result};
 use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread;
 
-use rasn::ber::{de, enc};
-use rasn::prelude::*;
-use rasn_ldap::{BindRequest, BindResponse, LdapMessage, MessageId, ProtocolOp, ResultCode};
+use rasn_ldap::ResultCode;
 
-fn main() -> Result<()> {
-    let listener = TcpListener::bind("127.0.0.1:8000")?;
-    let (mut stream, _) = listener.accept()?;
+use config::{Config, SecurityPolicy};
+use connection::LdapTcpConnection;
+use controller::LdapController;
+use health::Health;
+
+mod access;
+mod accounting;
+mod audit;
+mod ber;
+mod callout;
+mod config;
+mod connection;
+mod controller;
+mod controls;
+mod dn;
+mod entry;
+mod error;
+mod filter;
+mod health;
+mod log;
+mod monitor;
+mod password;
+mod queue;
+mod session;
+mod throttle;
 
-    let mut buf = [0; 1024];
-    stream.read(&mut buf)?;
+fn main() -> Result<()> {
+    let config = Config::from_args(env::args().skip(1))
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    log::set_max_level(config.log_level);
 
-    let mut ber_decoder = de::Decoder::new(&mut buf, de::DecoderOptions::ber());
-    let msg: LdapMessage = LdapMessage::decode(&mut ber_decoder).unwrap();
+    let listeners = config
+        .listeners
+        .iter()
+        .map(|l| TcpListener::bind(&l.addr).map(|listener| (listener, l.policy)))
+        .collect::<Result<Vec<_>>>()?;
+    let health_listener = config
+        .health_listen
+        .as_deref()
+        .map(TcpListener::bind)
+        .transpose()?;
+    let controller = Arc::new(LdapController::new(config)?);
+    let health = Arc::new(Health::new(listeners.len()));
 
-    let res = handle_ldap_message(msg);
+    let accept_threads: Vec<_> = listeners
+        .into_iter()
+        .map(|(listener, policy)| {
+            let controller = controller.clone();
+            let health = health.clone();
+            thread::spawn(move || {
+                let _accepting = health.accepting();
+                serve(listener, policy, controller)
+            })
+        })
+        .collect();
 
-    let mut ber_encoder = enc::Encoder::new(enc::EncoderOptions::ber());
-    res.encode(&mut ber_encoder).unwrap();
+    if let Some(listener) = health_listener {
+        let controller = controller.clone();
+        thread::spawn(move || health.serve(listener, &controller));
+    }
 
-    stream.write(ber_encoder.output().as_slice())?;
+    for t in accept_threads {
+        let _ = t.join();
+    }
 
     Ok(())
 }
 
-fn handle_ldap_message(msg: LdapMessage) -> LdapMessage {
-    match msg.protocol_op {
-        ProtocolOp::BindRequest(req) => handle_bind_request(msg.message_id, req),
-        _ => unimplemented!("That message type is unimplemented, handling unimplemented errors is also unimplemented!")
-    }
-}
+fn serve(listener: TcpListener, policy: SecurityPolicy, controller: Arc<LdapController>) {
+    for stream in listener.incoming() {
+        let (stream, peer) = match stream.and_then(|s| s.peer_addr().map(|peer| (s, peer))) {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                log::warn!("failed to accept connection: {e}");
+                continue;
+            }
+        };
+
+        let mut conn = LdapTcpConnection::new(stream, peer, policy, controller.clone());
+
+        let Some(guard) = controller.connections().try_open() else {
+            log::warn!(
+                "refusing connection from {peer}, {} connections already open",
+                controller.connections().open()
+            );
+            let _ = conn.disconnect(ResultCode::Busy, "too many connections");
+            continue;
+        };
 
-fn handle_bind_request(msg_id: MessageId, req: BindRequest) -> LdapMessage {
-    LdapMessage::new(
-        msg_id,
-        ProtocolOp::BindResponse(BindResponse::new(
-            ResultCode::Success,
-            req.name,
-            "not checking passwords".into(),
-            None,
-            None,
-        )),
-    )
+        // each connection gets its own thread, so an error or a panic while
+        // serving one client only ends that client's connection
+        thread::spawn(move || {
+            let _guard = guard;
+            let _span = log::span(format!("conn={} peer={peer}", conn.id()));
+            log::info!("connection opened");
+
+            if let Err(e) = conn.run() {
+                log::warn!("connection ended with error: {e}");
+            }
+
+            let traffic = conn.traffic();
+            log::info!(
+                "connection closed, {} bytes read, {} bytes written",
+                traffic.read,
+                traffic.written
+            );
+        });
+    }
 }
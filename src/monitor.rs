@@ -0,0 +1,178 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use rasn_ldap::{LdapMessage, ProtocolOp};
+
+use crate::entry::Entry;
+
+/// The base of the read-only subtree of runtime statistics, laid out like
+/// OpenLDAP's monitor backend so that tools which already scrape it work here
+pub const MONITOR_DN: &str = "cn=Monitor";
+
+/// The operations counted under `cn=Operations,cn=Monitor`, by their names there
+const OPERATIONS: [&str; 10] = [
+    "Bind", "Unbind", "Search", "Compare", "Modify", "Modrdn", "Add", "Delete", "Abandon",
+    "Extended",
+];
+
+/// Counts what the server has done since it started
+pub struct Monitor {
+    started: SystemTime,
+    initiated: [AtomicU64; OPERATIONS.len()],
+    completed: [AtomicU64; OPERATIONS.len()],
+    bytes_sent: AtomicU64,
+    pdus_sent: AtomicU64,
+    entries_sent: AtomicU64,
+}
+
+/// Figures the monitor reports that are kept elsewhere in the server
+pub struct Snapshot {
+    pub connections_total: u64,
+    pub connections_current: usize,
+    /// Operations being processed
+    pub executing: usize,
+    /// Operations waiting for a free slot to be processed in
+    pub queued: usize,
+}
+
+impl Monitor {
+    pub fn new(started: SystemTime) -> Self {
+        Monitor {
+            started,
+            initiated: Default::default(),
+            completed: Default::default(),
+            bytes_sent: AtomicU64::new(0),
+            pdus_sent: AtomicU64::new(0),
+            entries_sent: AtomicU64::new(0),
+        }
+    }
+
+    pub fn operation_initiated(&self, op: &ProtocolOp) {
+        if let Some(i) = operation_index(op) {
+            self.initiated[i].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn operation_completed(&self, op: &ProtocolOp) {
+        if let Some(i) = operation_index(op) {
+            self.completed[i].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_sent(&self, msg: &LdapMessage, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.pdus_sent.fetch_add(1, Ordering::Relaxed);
+        if let ProtocolOp::SearchResEntry(_) = msg.protocol_op {
+            self.entries_sent.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Builds every entry in the monitor subtree, from the root down
+    pub fn entries(&self, snapshot: Snapshot) -> Vec<Entry> {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let now = SystemTime::now();
+        let uptime = now.duration_since(self.started).unwrap_or_default();
+
+        let mut entries = vec![
+            Entry::new(MONITOR_DN)
+                .with("objectClass", "monitorServer")
+                .with("cn", "Monitor")
+                .with(
+                    "monitoredInfo",
+                    format!("lldap {}", env!("CARGO_PKG_VERSION")),
+                ),
+            container("Connections"),
+            counter("Total", "Connections", snapshot.connections_total),
+            counter("Current", "Connections", snapshot.connections_current),
+        ];
+
+        let (initiated, completed): (Vec<_>, Vec<_>) = self
+            .initiated
+            .iter()
+            .zip(&self.completed)
+            .map(|(i, c)| (load(i), load(c)))
+            .unzip();
+        entries.push(
+            container("Operations")
+                .with("monitorOpInitiated", initiated.iter().sum::<u64>())
+                .with("monitorOpCompleted", completed.iter().sum::<u64>()),
+        );
+        for (i, name) in OPERATIONS.iter().enumerate() {
+            entries.push(
+                Entry::new(format!("cn={name},cn=Operations,{MONITOR_DN}"))
+                    .with("objectClass", "monitorOperation")
+                    .with("cn", name)
+                    .with("monitorOpInitiated", initiated[i])
+                    .with("monitorOpCompleted", completed[i]),
+            );
+        }
+
+        entries.extend([
+            container("Statistics"),
+            counter("Bytes", "Statistics", load(&self.bytes_sent)),
+            counter("PDU", "Statistics", load(&self.pdus_sent)),
+            counter("Entries", "Statistics", load(&self.entries_sent)),
+            container("Time"),
+            timestamp("Start", self.started),
+            timestamp("Current", now),
+            Entry::new(format!("cn=Uptime,cn=Time,{MONITOR_DN}"))
+                .with("objectClass", "monitoredObject")
+                .with("cn", "Uptime")
+                .with("monitoredInfo", uptime.as_secs()),
+            container("Waiters"),
+            // connections that aren't in the middle of an operation are
+            // waiting to read their next request
+            counter(
+                "Read",
+                "Waiters",
+                snapshot
+                    .connections_current
+                    .saturating_sub(snapshot.executing + snapshot.queued),
+            ),
+            counter("Queue", "Waiters", snapshot.queued),
+        ]);
+
+        entries
+    }
+}
+
+fn operation_index(op: &ProtocolOp) -> Option<usize> {
+    let name = match op {
+        ProtocolOp::BindRequest(_) => "Bind",
+        ProtocolOp::UnbindRequest(_) => "Unbind",
+        ProtocolOp::SearchRequest(_) => "Search",
+        ProtocolOp::CompareRequest(_) => "Compare",
+        ProtocolOp::ModifyRequest(_) => "Modify",
+        ProtocolOp::ModDnRequest(_) => "Modrdn",
+        ProtocolOp::AddRequest(_) => "Add",
+        ProtocolOp::DelRequest(_) => "Delete",
+        ProtocolOp::AbandonRequest(_) => "Abandon",
+        ProtocolOp::ExtendedReq(_) => "Extended",
+        _ => return None,
+    };
+
+    OPERATIONS.iter().position(|op| *op == name)
+}
+
+fn container(name: &'static str) -> Entry {
+    Entry::new(format!("cn={name},{MONITOR_DN}"))
+        .with("objectClass", "monitorContainer")
+        .with("cn", name)
+}
+
+fn counter(name: &'static str, parent: &str, value: impl ToString) -> Entry {
+    Entry::new(format!("cn={name},cn={parent},{MONITOR_DN}"))
+        .with("objectClass", "monitorCounterObject")
+        .with("cn", name)
+        .with("monitorCounter", value)
+}
+
+fn timestamp(name: &'static str, time: SystemTime) -> Entry {
+    let time = DateTime::<Utc>::from(time).format("%Y%m%d%H%M%SZ");
+
+    Entry::new(format!("cn={name},cn=Time,{MONITOR_DN}"))
+        .with("objectClass", "monitoredObject")
+        .with("cn", name)
+        .with("monitorTimestamp", time)
+}
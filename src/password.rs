@@ -0,0 +1,13 @@
+use std::hint;
+
+/// Compares passwords without stopping at the first difference, so that how
+/// long it takes doesn't reveal how much of a guess was right. Only the
+/// length is given away.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let diff = a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y));
+    hint::black_box(diff) == 0
+}
@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+/// Bounds the number of operations being processed at once across all
+/// connections, so a load spike makes clients wait (and eventually get told
+/// the server is busy) rather than piling up work without limit
+pub struct OperationQueue {
+    max_in_flight: usize,
+    max_wait: Duration,
+    in_flight: Mutex<usize>,
+    freed: Condvar,
+    /// Operations waiting for a free slot
+    waiting: AtomicUsize,
+}
+
+/// A slot in the queue, freed when dropped
+pub struct OperationPermit<'a>(&'a OperationQueue);
+
+impl OperationQueue {
+    pub fn new(max_in_flight: usize, max_wait: Duration) -> Self {
+        OperationQueue {
+            max_in_flight,
+            max_wait,
+            in_flight: Mutex::new(0),
+            freed: Condvar::new(),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// Waits up to the configured time for a free slot, returning `None` if
+    /// the server is still saturated after that
+    pub fn acquire(&self) -> Option<OperationPermit<'_>> {
+        let in_flight = self.in_flight.lock().unwrap();
+        self.waiting.fetch_add(1, Ordering::Relaxed);
+        let (mut in_flight, _) = self
+            .freed
+            .wait_timeout_while(in_flight, self.max_wait, |n| *n >= self.max_in_flight)
+            .unwrap();
+        self.waiting.fetch_sub(1, Ordering::Relaxed);
+
+        if *in_flight >= self.max_in_flight {
+            return None;
+        }
+
+        *in_flight += 1;
+        Some(OperationPermit(self))
+    }
+
+    pub fn in_flight(&self) -> usize {
+        *self.in_flight.lock().unwrap()
+    }
+
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for OperationPermit<'_> {
+    fn drop(&mut self) {
+        *self.0.in_flight.lock().unwrap() -= 1;
+        self.0.freed.notify_one();
+    }
+}
@@ -0,0 +1,51 @@
+use std::net::SocketAddr;
+
+use rasn_ldap::{AuthenticationChoice, BindRequest, ResultCode};
+
+use crate::accounting::Traffic;
+use crate::config::SecurityPolicy;
+use crate::error::LdapError;
+
+/// Per-connection state that the controller needs when handling requests
+pub struct Session {
+    /// Identifies the connection in logs, unique for as long as the server runs
+    pub id: u64,
+    pub peer: SocketAddr,
+    /// Whether the connection is protected by TLS. The server can only accept
+    /// plaintext connections so far, so this is always false for now.
+    pub secure: bool,
+    /// The policy of the listener the connection was accepted on
+    pub policy: SecurityPolicy,
+    /// The DN the client last bound as, or `None` if it is anonymous
+    pub bind_dn: Option<String>,
+    pub traffic: Traffic,
+    /// Every identity the connection's traffic has been charged to, for
+    /// checking quotas
+    pub charged_identities: Vec<String>,
+}
+
+impl Session {
+    /// Checks a bind against the listener's policy, before any credentials in
+    /// it are looked at
+    pub fn check_bind_policy(&self, req: &BindRequest) -> Result<(), LdapError> {
+        let AuthenticationChoice::Simple(pw) = &req.authentication else {
+            return Ok(());
+        };
+
+        match (req.name.is_empty(), pw.is_empty()) {
+            (true, true) if !self.policy.anonymous => Err(LdapError::new(
+                ResultCode::InappropriateAuthentication,
+                "anonymous binds are not allowed",
+            )),
+            (false, true) if !self.policy.unauthenticated => Err(LdapError::new(
+                ResultCode::UnwillingToPerform,
+                "unauthenticated binds are not allowed",
+            )),
+            (_, false) if !self.policy.cleartext_binds && !self.secure => Err(LdapError::new(
+                ResultCode::ConfidentialityRequired,
+                "simple binds with a password require a secure connection",
+            )),
+            _ => Ok(()),
+        }
+    }
+}
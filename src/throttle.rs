@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rasn_ldap::ResultCode;
+
+use crate::dn;
+use crate::error::LdapError;
+
+/// Tracks failed binds per client address and per bind DN, refusing further
+/// attempts from an address or against a DN for a while once too many have
+/// failed within the window
+pub struct BindThrottle {
+    max_failures: u32,
+    window: Duration,
+    lockout: Duration,
+    by_ip: Mutex<HashMap<IpAddr, Failures>>,
+    by_dn: Mutex<HashMap<String, Failures>>,
+}
+
+struct Failures {
+    count: u32,
+    since: Instant,
+    /// When the lockout began, as adding a very long lockout to it could
+    /// overflow
+    locked_at: Option<Instant>,
+}
+
+impl BindThrottle {
+    /// A `max_failures` of zero turns throttling off
+    pub fn new(max_failures: u32, window: Duration, lockout: Duration) -> Self {
+        BindThrottle {
+            max_failures,
+            window,
+            lockout,
+            by_ip: Mutex::new(HashMap::new()),
+            by_dn: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn check(&self, ip: IpAddr, bind_dn: &str) -> Result<(), LdapError> {
+        let now = Instant::now();
+        let locked = |f: &Failures| self.is_locked(f, now);
+
+        let ip_locked = self.by_ip.lock().unwrap().get(&ip).is_some_and(locked);
+        let dn_locked = self
+            .by_dn
+            .lock()
+            .unwrap()
+            .get(&dn::normalise(bind_dn))
+            .is_some_and(locked);
+
+        if ip_locked || dn_locked {
+            return Err(LdapError::new(
+                ResultCode::UnwillingToPerform,
+                "too many failed binds, try again later",
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn record_failure(&self, ip: IpAddr, bind_dn: &str) {
+        if self.max_failures == 0 {
+            return;
+        }
+
+        self.record(&mut self.by_ip.lock().unwrap(), ip);
+        self.record(&mut self.by_dn.lock().unwrap(), dn::normalise(bind_dn));
+    }
+
+    /// Forgets the failures against a DN once someone binds to it successfully.
+    /// Failures from the client's address still count, so guessing at many
+    /// DNs is throttled even if one of them is the attacker's own.
+    pub fn record_success(&self, bind_dn: &str) {
+        self.by_dn.lock().unwrap().remove(&dn::normalise(bind_dn));
+    }
+
+    fn record<K: Hash + Eq>(&self, failures: &mut HashMap<K, Failures>, key: K) {
+        let now = Instant::now();
+
+        // drop anything that has aged out so the maps don't grow forever
+        failures.retain(|_, f| now.duration_since(f.since) < self.window || self.is_locked(f, now));
+
+        let f = failures.entry(key).or_insert(Failures {
+            count: 0,
+            since: now,
+            locked_at: None,
+        });
+
+        f.count += 1;
+        if f.count >= self.max_failures {
+            f.count = 0;
+            f.since = now;
+            f.locked_at = Some(now);
+        }
+    }
+
+    fn is_locked(&self, f: &Failures, now: Instant) -> bool {
+        f.locked_at
+            .is_some_and(|at| now.duration_since(at) < self.lockout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    fn ip(n: u8) -> IpAddr {
+        IpAddr::from([10, 0, 0, n])
+    }
+
+    #[test]
+    fn locks_out_after_max_failures() {
+        let throttle = BindThrottle::new(3, HOUR, HOUR);
+
+        throttle.record_failure(ip(1), "cn=a");
+        throttle.record_failure(ip(1), "cn=a");
+        assert!(throttle.check(ip(1), "cn=a").is_ok());
+
+        throttle.record_failure(ip(1), "CN=A");
+        let err = throttle.check(ip(1), "cn=a").unwrap_err();
+        assert_eq!(err.code, ResultCode::UnwillingToPerform);
+
+        // both the address and the DN are locked out
+        assert!(throttle.check(ip(1), "cn=b").is_err());
+        assert!(throttle.check(ip(2), "cn=a").is_err());
+        assert!(throttle.check(ip(2), "cn=b").is_ok());
+    }
+
+    #[test]
+    fn failures_expire_after_the_window() {
+        let throttle = BindThrottle::new(2, Duration::from_millis(50), HOUR);
+
+        throttle.record_failure(ip(1), "cn=a");
+        thread::sleep(Duration::from_millis(60));
+        throttle.record_failure(ip(1), "cn=a");
+        assert!(throttle.check(ip(1), "cn=a").is_ok());
+
+        throttle.record_failure(ip(1), "cn=a");
+        assert!(throttle.check(ip(1), "cn=a").is_err());
+    }
+
+    #[test]
+    fn lockouts_expire() {
+        let throttle = BindThrottle::new(1, HOUR, Duration::from_millis(50));
+
+        throttle.record_failure(ip(1), "cn=a");
+        assert!(throttle.check(ip(1), "cn=a").is_err());
+        thread::sleep(Duration::from_millis(60));
+        assert!(throttle.check(ip(1), "cn=a").is_ok());
+    }
+
+    #[test]
+    fn long_lockouts_never_expire() {
+        let throttle = BindThrottle::new(1, HOUR, Duration::MAX);
+
+        throttle.record_failure(ip(1), "cn=a");
+        assert!(throttle.check(ip(1), "cn=a").is_err());
+    }
+
+    #[test]
+    fn success_only_clears_the_dn() {
+        let throttle = BindThrottle::new(2, HOUR, HOUR);
+
+        throttle.record_failure(ip(1), "cn=a");
+        throttle.record_success("cn=a");
+
+        // the failure against cn=a is forgotten, but not the one from the address
+        throttle.record_failure(ip(2), "cn=a");
+        assert!(throttle.check(ip(3), "cn=a").is_ok());
+        throttle.record_failure(ip(1), "cn=b");
+        assert!(throttle.check(ip(1), "cn=c").is_err());
+    }
+
+    #[test]
+    fn zero_max_failures_disables_throttling() {
+        let throttle = BindThrottle::new(0, HOUR, HOUR);
+
+        for _ in 0..10 {
+            throttle.record_failure(ip(1), "cn=a");
+        }
+        assert!(throttle.check(ip(1), "cn=a").is_ok());
+    }
+}